@@ -1,16 +1,31 @@
 use crate::converters;
-use crate::models::{ConversionResult, ConversionError};
+use crate::converters::registry::{registry, Converted, FormatInfo, FormatKind};
+use crate::converters::sniff::DetectedFormat;
+use crate::converters::video::progress::Progress;
+use crate::models::{ConversionOptions, ConversionResult, ProgressUpdate};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tauri::{AppHandle, Emitter};
+
+/// Event carrying a [`ProgressUpdate`] for a running conversion.
+const PROGRESS_EVENT: &str = "conversion-progress";
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn convert_image(
+    app: AppHandle,
     input_data: Vec<u8>,
     output_path: String,
     from_format: String,
     to_format: String,
-    _keep_transparency: bool,
+    keep_transparency: bool,
+    options: Option<ConversionOptions>,
+    job_id: Option<String>,
 ) -> Result<ConversionResult, String> {
+    let id = job_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let mut options = options.unwrap_or_default();
+    options.transparency.keep = keep_transparency;
     let temp_dir = std::env::temp_dir();
     let temp_input = temp_dir.join(format!("cnvrt_input_{}.{}", uuid::Uuid::new_v4(), from_format.to_lowercase()));
     
@@ -18,7 +33,7 @@ pub async fn convert_image(
         Ok(mut file) => {
             if let Err(e) = file.write_all(&input_data) {
                 return Ok(ConversionResult {
-                    id: uuid::Uuid::new_v4().to_string(),
+                    id,
                     success: false,
                     message: format!("Failed to write input file: {}", e),
                     output_path: None,
@@ -27,7 +42,7 @@ pub async fn convert_image(
         }
         Err(e) => {
             return Ok(ConversionResult {
-                id: uuid::Uuid::new_v4().to_string(),
+                id,
                 success: false,
                 message: format!("Failed to create temp file: {}", e),
                 output_path: None,
@@ -46,49 +61,19 @@ pub async fn convert_image(
         output_path.clone()
     };
     
-    let to_format_lower = to_format.to_lowercase();
-    
-    let result = if to_format_lower == "gif" {
-        converters::utils::convert_any_image_format(&temp_input_str, &final_output_path, &to_format)
-    } else {
-        match from_format.to_lowercase().as_str() {
-            "png" => converters::png::convert_png(&temp_input_str, &final_output_path, &to_format),
-            "jpg" | "jpeg" | "jfif" => converters::jpg::convert_jpg(&temp_input_str, &final_output_path, &to_format),
-            "webp" => converters::webp::convert_webp(&temp_input_str, &final_output_path, &to_format),
-            "bmp" => converters::bmp::convert_bmp(&temp_input_str, &final_output_path, &to_format),
-            "gif" => converters::gif::convert_gif(&temp_input_str, &final_output_path, &to_format),
-            "tiff" | "tif" => converters::tiff::convert_tiff(&temp_input_str, &final_output_path, &to_format),
-            "ico" => converters::ico::convert_ico(&temp_input_str, &final_output_path, &to_format),
-            "avif" => converters::avif::convert_avif(&temp_input_str, &final_output_path, &to_format),
-            "heic" | "heif" => converters::heic::convert_heic(&temp_input_str, &final_output_path, &to_format),
-            "ppm" | "pgm" | "pbm" => converters::ppm::convert_ppm(&temp_input_str, &final_output_path, &to_format),
-            "tga" => converters::tga::convert_tga(&temp_input_str, &final_output_path, &to_format),
-            "dds" => converters::dds::convert_dds(&temp_input_str, &final_output_path, &to_format),
-            "apng" => converters::apng::convert_apng(&temp_input_str, &final_output_path, &to_format),
-            "cur" => converters::cur::convert_cur(&temp_input_str, &final_output_path, &to_format),
-            "exr" => converters::exr::convert_exr(&temp_input_str, &final_output_path, &to_format),
-            "svg" => converters::svg::convert_svg(&temp_input_str, &final_output_path, &to_format),
-            "pdf" => converters::pdf::convert_pdf(&temp_input_str, &final_output_path, &to_format),
-            "psd" | "psb" => converters::psd::convert_psd(&temp_input_str, &final_output_path, &to_format),
-            "fits" => converters::fits::convert_fits(&temp_input_str, &final_output_path, &to_format),
-            "dcm" => converters::dcm::convert_dcm(&temp_input_str, &final_output_path, &to_format),
-            "pcx" => converters::pcx::convert_pcx(&temp_input_str, &final_output_path, &to_format),
-            "mp4" | "m4v" | "mkv" | "mov" | "webm" | "avi" | "flv" | "mpg" | "mpeg" | "ts" | "m2ts" | "mts" | "ogv" | "ogg" => {
-                converters::video::transcoder::convert_video(&temp_input_str, &final_output_path, &to_format)
-            }
-            _ => Err(ConversionError::UnsupportedFormat(from_format.clone())),
-        }
-    };
+    let from_format = converters::sniff::resolve_format(&temp_input, &from_format);
+    let on_progress = progress_reporter(&app, &id);
+    let result = converters::registry::convert(&temp_input_str, &final_output_path, &from_format, &to_format, &options, &on_progress);
 
     match result {
-        Ok(_) => Ok(ConversionResult {
-            id: uuid::Uuid::new_v4().to_string(),
+        Ok(converted) => Ok(ConversionResult {
+            id,
             success: true,
-            message: format!("Successfully converted {} to {}", from_format, to_format),
-            output_path: Some(final_output_path),
+            message: success_message(&from_format, &to_format, &converted),
+            output_path: converted.files.first().map(|p| p.to_string_lossy().to_string()).or(Some(final_output_path)),
         }),
         Err(e) => Ok(ConversionResult {
-            id: uuid::Uuid::new_v4().to_string(),
+            id,
             success: false,
             message: e.to_string(),
             output_path: None,
@@ -96,6 +81,39 @@ pub async fn convert_image(
     }
 }
 
+fn success_message(from_format: &str, to_format: &str, converted: &Converted) -> String {
+    let mut message = if converted.files.len() > 1 {
+        format!("Successfully converted {} to {} ({} files)", from_format, to_format, converted.files.len())
+    } else {
+        format!("Successfully converted {} to {}", from_format, to_format)
+    };
+    for note in &converted.notes {
+        message.push_str("; ");
+        message.push_str(note);
+    }
+    message
+}
+
+/// Emits a [`ProgressUpdate`] for conversion `id` whenever the job reports
+/// progress, with an ETA from the time taken so far.
+fn progress_reporter(app: &AppHandle, id: &str) -> impl Fn(Progress) {
+    let (app, id, started) = (app.clone(), id.to_string(), Instant::now());
+    move |progress| {
+        let percentage = (progress.fraction * 100.0).round() as u32;
+        let mut message = format!("Converting... {}%", percentage);
+        if let Some(speed) = progress.speed {
+            message.push_str(&format!(" ({:.1}x)", speed));
+        }
+        let update = ProgressUpdate {
+            id: id.clone(),
+            percentage,
+            message,
+            eta_seconds: progress.eta(started.elapsed()).map(|eta| eta.as_secs_f32().round() as u64),
+        };
+        let _ = app.emit(PROGRESS_EVENT, update);
+    }
+}
+
 #[tauri::command]
 pub fn get_supported_formats(format: String) -> Vec<String> {
     crate::converters::utils::get_supported_formats(&format)
@@ -106,6 +124,16 @@ pub fn is_supported_format(format: String) -> bool {
     crate::converters::utils::is_image_format(&format) || crate::converters::utils::is_video_format(&format)
 }
 
+#[tauri::command]
+pub fn get_format_catalog() -> Vec<FormatInfo> {
+    registry().formats().cloned().collect()
+}
+
+#[tauri::command]
+pub fn detect_format(path: String) -> Result<Option<DetectedFormat>, String> {
+    converters::sniff::detect_file(Path::new(&path)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn select_folder() -> Option<String> {
     rfd::FileDialog::new()
@@ -121,71 +149,49 @@ pub fn get_downloads_folder() -> Option<String> {
 
 #[tauri::command]
 pub fn pick_files() -> Option<Vec<String>> {
+    let images = registry().input_extensions(FormatKind::Image);
+    let videos = registry().input_extensions(FormatKind::Video);
+    let all: Vec<String> = images.iter().chain(videos.iter()).cloned().collect();
+
     rfd::FileDialog::new()
-        .add_filter("All Supported", &[
-            "png", "jpg", "jpeg", "webp", "bmp", "gif", "tiff", "tif", "ico", "avif", "heic", "heif",
-            "mp4", "mkv", "mov", "webm", "avi", "flv", "mpg", "mpeg", "ts", "m2ts", "mts", "ogv", "ogg"
-        ])
-        .add_filter("Images", &["png", "jpg", "jpeg", "webp", "bmp", "gif", "tiff", "tif", "ico", "avif", "heic", "heif"])
-        .add_filter("Videos", &["mp4", "mkv", "mov", "webm", "avi", "flv", "mpg", "mpeg", "ts", "m2ts", "mts", "ogv", "ogg"])
+        .add_filter("All Supported", &all)
+        .add_filter("Images", &images)
+        .add_filter("Videos", &videos)
         .add_filter("All Files", &["*"])
         .pick_files()
         .map(|paths| paths.iter().map(|p| p.to_string_lossy().to_string()).collect())
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn convert_from_path(
+    app: AppHandle,
     input_path: String,
     output_path: String,
     from_format: String,
     to_format: String,
+    keep_transparency: Option<bool>,
+    options: Option<ConversionOptions>,
+    job_id: Option<String>,
 ) -> Result<ConversionResult, String> {
-    let target_lower = to_format.to_lowercase();
-    let from_lower = from_format.to_lowercase();
-    
-    let result = if target_lower == "gif" {
-        crate::converters::utils::convert_any_image_format(&input_path, &output_path, &to_format)
-    } else {
-        match from_lower.as_str() {
-            // Image formats
-            "png" => crate::converters::png::convert_png(&input_path, &output_path, &to_format),
-            "jpg" | "jpeg" | "jfif" => crate::converters::jpg::convert_jpg(&input_path, &output_path, &to_format),
-            "webp" => crate::converters::webp::convert_webp(&input_path, &output_path, &to_format),
-            "bmp" => crate::converters::bmp::convert_bmp(&input_path, &output_path, &to_format),
-            "gif" => crate::converters::gif::convert_gif(&input_path, &output_path, &to_format),
-            "tiff" | "tif" => crate::converters::tiff::convert_tiff(&input_path, &output_path, &to_format),
-            "ico" => crate::converters::ico::convert_ico(&input_path, &output_path, &to_format),
-            "avif" => crate::converters::avif::convert_avif(&input_path, &output_path, &to_format),
-            "heic" | "heif" => crate::converters::heic::convert_heic(&input_path, &output_path, &to_format),
-            "ppm" | "pgm" | "pbm" => crate::converters::ppm::convert_ppm(&input_path, &output_path, &to_format),
-            "tga" => crate::converters::tga::convert_tga(&input_path, &output_path, &to_format),
-            "dds" => crate::converters::dds::convert_dds(&input_path, &output_path, &to_format),
-            "apng" => crate::converters::apng::convert_apng(&input_path, &output_path, &to_format),
-            "cur" => crate::converters::cur::convert_cur(&input_path, &output_path, &to_format),
-            "exr" => crate::converters::exr::convert_exr(&input_path, &output_path, &to_format),
-            "svg" => crate::converters::svg::convert_svg(&input_path, &output_path, &to_format),
-            "pdf" => crate::converters::pdf::convert_pdf(&input_path, &output_path, &to_format),
-            "psd" | "psb" => crate::converters::psd::convert_psd(&input_path, &output_path, &to_format),
-            "fits" => crate::converters::fits::convert_fits(&input_path, &output_path, &to_format),
-            "dcm" => crate::converters::dcm::convert_dcm(&input_path, &output_path, &to_format),
-            "pcx" => crate::converters::pcx::convert_pcx(&input_path, &output_path, &to_format),
-            // Video formats
-            "mp4" | "m4v" | "mkv" | "mov" | "webm" | "avi" | "flv" | "mpg" | "mpeg" | "ts" | "m2ts" | "mts" | "ogv" | "ogg" => {
-                crate::converters::video::transcoder::convert_video(&input_path, &output_path, &to_format)
-            }
-            _ => Err(ConversionError::UnsupportedFormat(from_format.clone())),
-        }
-    };
+    let id = job_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let mut options = options.unwrap_or_default();
+    if let Some(keep) = keep_transparency {
+        options.transparency.keep = keep;
+    }
+    let from_format = converters::sniff::resolve_format(Path::new(&input_path), &from_format);
+    let on_progress = progress_reporter(&app, &id);
+    let result = converters::registry::convert(&input_path, &output_path, &from_format, &to_format, &options, &on_progress);
 
     match result {
-        Ok(_) => Ok(ConversionResult {
-            id: uuid::Uuid::new_v4().to_string(),
+        Ok(converted) => Ok(ConversionResult {
+            id,
             success: true,
-            message: format!("Successfully converted {} to {}", from_format, to_format),
-            output_path: Some(output_path),
+            message: success_message(&from_format, &to_format, &converted),
+            output_path: converted.files.first().map(|p| p.to_string_lossy().to_string()).or(Some(output_path)),
         }),
         Err(e) => Ok(ConversionResult {
-            id: uuid::Uuid::new_v4().to_string(),
+            id,
             success: false,
             message: format!("Conversion error: {}", e),
             output_path: None,
@@ -0,0 +1,133 @@
+//! Animated GIF, APNG and WebP. Frames are held composited onto the full
+//! canvas, with each source frame's disposal and blending already applied,
+//! so any of the three formats can be written from any other.
+
+use image::{AnimationDecoder, DynamicImage, RgbaImage};
+use std::time::Duration;
+use super::{transform, transparency};
+use crate::models::{ConversionError, ConversionOptions, TransparencyOptions};
+
+/// Display time given to frames that record none, as browsers do.
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Frame rates accepted when resampling an animation.
+pub const FRAME_RATES: std::ops::RangeInclusive<f32> = 0.1..=100.0;
+
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    pub image: RgbaImage,
+    pub delay: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    /// Times the animation plays; 0 loops forever.
+    pub loop_count: u32,
+}
+
+impl Animation {
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames.first().map_or((0, 0), |frame| frame.image.dimensions())
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.frames.iter().any(|frame| frame.image.pixels().any(|p| p[3] < 255))
+    }
+}
+
+/// Collects every frame from one of the `image` animation decoders.
+pub fn decode<'a>(decoder: impl AnimationDecoder<'a>, loop_count: u32) -> Result<Animation, ConversionError> {
+    let frames = decoder
+        .into_frames()
+        .collect_frames()
+        .map_err(|e| ConversionError::ImageError(format!("Failed to decode animation frame: {}", e)))?
+        .into_iter()
+        .map(|frame| {
+            let delay = Duration::from(frame.delay());
+            AnimationFrame {
+                image: frame.into_buffer(),
+                delay: if delay.is_zero() { DEFAULT_DELAY } else { delay },
+            }
+        })
+        .collect();
+    Ok(Animation { frames, loop_count })
+}
+
+/// Applies the animation options, then the same transform and transparency
+/// handling still images get, to every frame.
+pub fn prepare(animation: Animation, target_has_alpha: bool, options: &ConversionOptions) -> Result<Animation, ConversionError> {
+    let settings = &options.animation;
+    if settings.scale.is_nan() || settings.scale <= 0.0 {
+        return Err(ConversionError::ConversionFailed("Animation scale must be greater than 0".to_string()));
+    }
+
+    let frames = match settings.frame_rate {
+        Some(fps) => resample(animation.frames, check_frame_rate(fps)?),
+        None => animation.frames,
+    };
+
+    // Trimming each frame to its own content would leave them different sizes.
+    let transparency = TransparencyOptions { trim: false, ..options.transparency.clone() };
+    let filter = transform::filter(options.transform.filter);
+    let frames = frames
+        .into_iter()
+        .map(|frame| {
+            let mut image = transform::apply(DynamicImage::ImageRgba8(frame.image), &options.transform)?;
+            if settings.scale != 1.0 {
+                let (width, height) = transform::scaled(image.width(), image.height(), settings.scale as f64);
+                image = image.resize_exact(width, height, filter);
+            }
+            let image = transparency::apply(image, target_has_alpha, &transparency).to_rgba8();
+            Ok(AnimationFrame { image, delay: frame.delay })
+        })
+        .collect::<Result<_, ConversionError>>()?;
+
+    Ok(Animation {
+        frames,
+        loop_count: settings.loop_count.unwrap_or(animation.loop_count),
+    })
+}
+
+/// Rejects frame rates outside [`FRAME_RATES`].
+pub fn check_frame_rate(fps: f32) -> Result<f32, ConversionError> {
+    if !FRAME_RATES.contains(&fps) {
+        return Err(ConversionError::ConversionFailed(format!(
+            "Animation frame rate must be between {} and {} fps",
+            FRAME_RATES.start(),
+            FRAME_RATES.end()
+        )));
+    }
+    Ok(fps)
+}
+
+/// Samples the source timeline every `1 / fps` seconds, so frames are dropped
+/// or repeated while the running time stays the same. Consecutive samples of
+/// the same frame are merged into one longer frame.
+fn resample(frames: Vec<AnimationFrame>, fps: f32) -> Vec<AnimationFrame> {
+    if frames.is_empty() {
+        return frames;
+    }
+    let step = Duration::from_secs_f32(1.0 / fps);
+    let total: Duration = frames.iter().map(|frame| frame.delay).sum();
+    let samples = ((total.as_secs_f64() / step.as_secs_f64()).round() as usize).max(1);
+
+    let mut resampled: Vec<(usize, Duration)> = Vec::new();
+    let (mut index, mut frame_end) = (0, frames[0].delay);
+    for sample in 0..samples {
+        let time = Duration::from_secs_f64(sample as f64 / fps as f64);
+        while time >= frame_end && index + 1 < frames.len() {
+            index += 1;
+            frame_end += frames[index].delay;
+        }
+        match resampled.last_mut() {
+            Some((last, delay)) if *last == index => *delay += step,
+            _ => resampled.push((index, step)),
+        }
+    }
+
+    resampled
+        .into_iter()
+        .map(|(index, delay)| AnimationFrame { image: frames[index].image.clone(), delay })
+        .collect()
+}
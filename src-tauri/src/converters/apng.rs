@@ -1,40 +1,105 @@
-use image::ImageReader;
+use image::codecs::png::PngDecoder;
+use image::{DynamicImage, ImageEncoder};
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
 use std::path::Path;
-use crate::models::ConversionError;
+use super::animation::{self, Animation};
+use super::png;
+use super::registry::{self, Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions};
 
-pub fn convert_apng(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("apng", "APNG").options(&["png"]).encodable().alpha().animated();
+
+/// `fcTL` dispose and blend ops: leave the canvas, and replace rather than
+/// composite, since every frame is a full canvas.
+const DISPOSE_NONE: u8 = 0;
+const BLEND_SOURCE: u8 = 0;
+
+pub struct ApngConverter;
+
+impl Converter for ApngConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    /// The default image, i.e. the first frame.
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        registry::read_image(input_path, options)
+    }
+
+    /// A single image is an ordinary PNG, which every APNG reader shows.
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let rgba = img.to_rgba8();
+        png::encoder(BufWriter::new(File::create(output_path)?), options)
+            .write_image(rgba.as_raw(), rgba.width(), rgba.height(), image::ExtendedColorType::Rgba8)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save APNG: {}", e)))
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    fn decode_animation(&self, input_path: &Path, _options: &ConversionOptions) -> Result<Option<Animation>, ConversionError> {
+        let read_error = |e: image::ImageError| ConversionError::ImageError(format!("Failed to read APNG: {}", e));
+        let data = std::fs::read(input_path)?;
+        let decoder = PngDecoder::new(Cursor::new(&data)).and_then(|decoder| decoder.apng()).map_err(read_error)?;
+        animation::decode(decoder, loop_count(&data)).map(Some)
     }
 
-    Ok(())
+    /// Encodes each frame as a PNG and re-labels its `IDAT` data: the first
+    /// frame doubles as the default image, later ones go in `fdAT` chunks.
+    fn encode_animation(&self, animation: &Animation, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let (width, height) = animation.dimensions();
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut actl = (animation.frames.len() as u32).to_be_bytes().to_vec();
+        actl.extend_from_slice(&animation.loop_count.to_be_bytes());
+        let mut sequence = 0u32;
+
+        for (index, frame) in animation.frames.iter().enumerate() {
+            let mut encoded = Vec::new();
+            png::encoder(&mut encoded, options)
+                .write_image(frame.image.as_raw(), width, height, image::ExtendedColorType::Rgba8)
+                .map_err(|e| ConversionError::ImageError(format!("Failed to encode APNG frame {}: {}", index + 1, e)))?;
+            let chunks = png::read_chunks(&encoded);
+
+            if index == 0 {
+                let (_, ihdr) = chunks.iter().find(|(kind, _)| kind == b"IHDR")
+                    .ok_or_else(|| ConversionError::ImageError("Encoded frame has no IHDR".to_string()))?;
+                png::push_chunk(&mut out, b"IHDR", ihdr);
+                png::push_chunk(&mut out, b"acTL", &actl);
+            }
+
+            // Delays are a fraction of a second; milliseconds fit in most cases.
+            let delay = frame.delay.as_millis().min(u16::MAX as u128) as u16;
+            let mut fctl = sequence.to_be_bytes().to_vec();
+            for value in [width, height, 0, 0] {
+                fctl.extend_from_slice(&value.to_be_bytes());
+            }
+            fctl.extend_from_slice(&delay.to_be_bytes());
+            fctl.extend_from_slice(&1000u16.to_be_bytes());
+            fctl.extend_from_slice(&[DISPOSE_NONE, BLEND_SOURCE]);
+            png::push_chunk(&mut out, b"fcTL", &fctl);
+            sequence += 1;
+
+            for (_, idat) in chunks.iter().filter(|(kind, _)| kind == b"IDAT") {
+                if index == 0 {
+                    png::push_chunk(&mut out, b"IDAT", idat);
+                } else {
+                    let mut fdat = sequence.to_be_bytes().to_vec();
+                    fdat.extend_from_slice(idat);
+                    png::push_chunk(&mut out, b"fdAT", &fdat);
+                    sequence += 1;
+                }
+            }
+        }
+
+        png::push_chunk(&mut out, b"IEND", &[]);
+        std::fs::write(output_path, out)?;
+        Ok(())
+    }
+}
+
+/// `num_plays` from the `acTL` chunk; 0 is forever.
+fn loop_count(data: &[u8]) -> u32 {
+    png::read_chunks(data)
+        .into_iter()
+        .find(|(kind, _)| kind == b"acTL")
+        .and_then(|(_, body)| body.get(4..8))
+        .map_or(0, |plays| u32::from_be_bytes([plays[0], plays[1], plays[2], plays[3]]))
 }
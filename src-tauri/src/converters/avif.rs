@@ -1,48 +1,58 @@
-use image::ImageReader;
+use image::DynamicImage;
+use libheif_rs::{CompressionFormat, EncoderParameterValue, EncoderQuality, HeifContext, HeifError, LibHeif};
 use std::path::Path;
-use crate::models::ConversionError;
+use super::heif;
+use super::metadata::Metadata;
+use super::registry::{Converter, FormatInfo};
+use crate::models::{AvifOptions, ConversionError, ConversionOptions};
 
-pub fn convert_avif(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("avif", "AVIF").options(&["avif"]).encodable().alpha().bit_depth(10);
+
+/// AVIF is an AV1 payload in a HEIF container, so both directions go through
+/// libheif and whichever AV1 codecs it was built with (dav1d/aom/rav1e/SVT).
+pub struct AvifConverter;
+
+impl Converter for AvifConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn metadata(&self, input_path: &Path) -> Metadata {
+        heif::read_metadata(input_path)
+    }
+
+    fn output_bit_depth(&self, options: &ConversionOptions) -> u8 {
+        if options.avif.ten_bit { 10 } else { 8 }
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        heif::decode_primary(input_path, options)
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        encode(img, output_path, &options.avif)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save AVIF: {}", e)))
     }
+}
+
+fn encode(img: &DynamicImage, output_path: &Path, options: &AvifOptions) -> Result<(), HeifError> {
+    let lib_heif = LibHeif::new();
+    let mut encoder = lib_heif.encoder_for_format(CompressionFormat::Av1)?;
+
+    encoder.set_quality(EncoderQuality::Lossy(options.quality.min(100)))?;
+    // Parameter names are shared by libheif's aom, rav1e and SVT-AV1 plugins,
+    // but not every plugin exposes all of them.
+    if encoder.parameter("speed")?.is_some() {
+        encoder.set_parameter_value("speed", EncoderParameterValue::Int(options.speed.min(9) as i32))?;
+    }
+    if encoder.parameter("chroma")?.is_some() {
+        encoder.set_parameter_value("chroma", EncoderParameterValue::String(options.chroma.as_str().to_string()))?;
+    }
+
+    let bit_depth = if options.ten_bit { 10 } else { 8 };
+    let image = heif::to_heif_image(img, bit_depth)?;
 
-    Ok(())
+    let mut ctx = HeifContext::new()?;
+    ctx.encode_image(&image, &mut encoder, None)?;
+    ctx.write_to_file(&output_path.to_string_lossy())
 }
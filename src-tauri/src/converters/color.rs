@@ -0,0 +1,140 @@
+//! ICC colour management through Little CMS.
+
+use image::DynamicImage;
+use lcms2::{
+    CIExyY, CIExyYTRIPLE, ColorSpaceSignature, Flags, Intent, Locale, PixelFormat, Pod, Profile, Tag, TagSignature, ToneCurve,
+    Transform, MLU,
+};
+use crate::models::{ColorOptions, ConversionError, RenderingIntent, TargetProfile};
+
+/// CIE xy of the D65 white point shared by sRGB, Display P3 and Adobe RGB.
+const D65: CIExyY = CIExyY { x: 0.3127, y: 0.3290, Y: 1.0 };
+
+/// Converts pages to the target profile when requested, and returns the ICC
+/// profile that describes the pixels that come out: the target after a
+/// conversion, otherwise whatever the source carried.
+pub fn apply(
+    pages: Vec<DynamicImage>,
+    source_icc: Option<Vec<u8>>,
+    options: &ColorOptions,
+) -> Result<(Vec<DynamicImage>, Option<Vec<u8>>), ConversionError> {
+    if !options.convert {
+        return Ok((pages, source_icc));
+    }
+
+    let target = target_profile(options)?;
+    let target_icc = target.icc().map_err(|e| failed(format!("Failed to serialise the target ICC profile: {}", e)))?;
+
+    // Profiles for other colour spaces (e.g. CMYK JPEGs the decoder already
+    // turned into RGB) no longer describe the pixels, so fall back to sRGB.
+    let source = source_icc
+        .as_deref()
+        .and_then(|icc| Profile::new_icc(icc).ok())
+        .filter(|profile| profile.color_space() == ColorSpaceSignature::RgbData);
+    let source = match source {
+        Some(profile) => profile,
+        None if options.target == TargetProfile::Srgb && options.target_profile_path.is_none() => {
+            return Ok((pages, Some(target_icc)));
+        }
+        None => Profile::new_srgb(),
+    };
+
+    let intent = match options.intent {
+        RenderingIntent::Perceptual => Intent::Perceptual,
+        RenderingIntent::RelativeColorimetric => Intent::RelativeColorimetric,
+        RenderingIntent::Saturation => Intent::Saturation,
+        RenderingIntent::AbsoluteColorimetric => Intent::AbsoluteColorimetric,
+    };
+
+    let pages = pages
+        .into_iter()
+        .map(|page| convert(page, &source, &target, intent))
+        .collect::<Result<_, _>>()?;
+    Ok((pages, Some(target_icc)))
+}
+
+/// Whether `icc` can be embedded with `page`: an RGB profile for colour
+/// pixels, a Gray one for greyscale. Decoding and flattening can change the
+/// layout after the profile was read, e.g. CMYK JPEGs decode to RGB and grey
+/// images with alpha are flattened to RGB.
+pub fn describes(icc: &[u8], page: &DynamicImage) -> bool {
+    let wanted = if page.color().has_color() { ColorSpaceSignature::RgbData } else { ColorSpaceSignature::GrayData };
+    Profile::new_icc(icc).is_ok_and(|profile| profile.color_space() == wanted)
+}
+
+fn failed(message: String) -> ConversionError {
+    ConversionError::ConversionFailed(message)
+}
+
+fn target_profile(options: &ColorOptions) -> Result<Profile, ConversionError> {
+    if let Some(path) = &options.target_profile_path {
+        let profile = Profile::new_file(path).map_err(|e| failed(format!("Failed to read ICC profile {}: {}", path, e)))?;
+        if profile.color_space() != ColorSpaceSignature::RgbData {
+            return Err(failed(format!("ICC profile {} does not describe RGB colour", path)));
+        }
+        return Ok(profile);
+    }
+
+    let xy = |x: f64, y: f64| CIExyY { x, y, Y: 1.0 };
+    let (name, primaries, curve) = match options.target {
+        TargetProfile::Srgb => return Ok(Profile::new_srgb()),
+        TargetProfile::DisplayP3 => {
+            // P3 primaries with the sRGB transfer curve.
+            let curve = ToneCurve::new_parametric(4, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])
+                .map_err(|e| failed(format!("Failed to build the Display P3 curve: {}", e)))?;
+            ("Display P3", [xy(0.680, 0.320), xy(0.265, 0.690), xy(0.150, 0.060)], curve)
+        }
+        TargetProfile::AdobeRgb => ("Adobe RGB (1998)", [xy(0.64, 0.33), xy(0.21, 0.71), xy(0.15, 0.06)], ToneCurve::new(563.0 / 256.0)),
+    };
+
+    let [red, green, blue] = primaries;
+    let primaries = CIExyYTRIPLE { Red: red, Green: green, Blue: blue };
+    let mut profile = Profile::new_rgb(&D65, &primaries, &[&curve, &curve, &curve])
+        .map_err(|e| failed(format!("Failed to build the {} profile: {}", name, e)))?;
+
+    let mut description = MLU::new(1);
+    description.set_text_ascii(name, Locale::none());
+    profile.write_tag(TagSignature::ProfileDescriptionTag, Tag::MLU(&description));
+    Ok(profile)
+}
+
+/// Transforms in place at the image's own depth. Greyscale is promoted to
+/// RGB, since the target gamut can't be expressed in one channel; alpha is
+/// carried through unchanged.
+fn convert(img: DynamicImage, source: &Profile, target: &Profile, intent: Intent) -> Result<DynamicImage, ConversionError> {
+    let mut img = match img {
+        DynamicImage::ImageLuma8(_) => DynamicImage::ImageRgb8(img.to_rgb8()),
+        DynamicImage::ImageLumaA8(_) => DynamicImage::ImageRgba8(img.to_rgba8()),
+        DynamicImage::ImageLuma16(_) => DynamicImage::ImageRgb16(img.to_rgb16()),
+        DynamicImage::ImageLumaA16(_) => DynamicImage::ImageRgba16(img.to_rgba16()),
+        other => other,
+    };
+
+    match &mut img {
+        DynamicImage::ImageRgb8(buf) => transform::<u8, 3>(buf, PixelFormat::RGB_8, source, target, intent)?,
+        DynamicImage::ImageRgba8(buf) => transform::<u8, 4>(buf, PixelFormat::RGBA_8, source, target, intent)?,
+        DynamicImage::ImageRgb16(buf) => transform::<u16, 3>(buf, PixelFormat::RGB_16, source, target, intent)?,
+        DynamicImage::ImageRgba16(buf) => transform::<u16, 4>(buf, PixelFormat::RGBA_16, source, target, intent)?,
+        DynamicImage::ImageRgb32F(buf) => transform::<f32, 3>(buf, PixelFormat::RGB_FLT, source, target, intent)?,
+        DynamicImage::ImageRgba32F(buf) => transform::<f32, 4>(buf, PixelFormat::RGBA_FLT, source, target, intent)?,
+        _ => return Err(failed("Unsupported pixel layout for colour conversion".to_string())),
+    }
+    Ok(img)
+}
+
+fn transform<T: Pod, const N: usize>(
+    samples: &mut [T],
+    format: PixelFormat,
+    source: &Profile,
+    target: &Profile,
+    intent: Intent,
+) -> Result<(), ConversionError>
+where
+    [T; N]: Pod,
+{
+    let transform = Transform::<[T; N], [T; N]>::new_flags(source, format, target, format, intent, Flags::COPY_ALPHA)
+        .map_err(|e| failed(format!("Failed to create colour transform: {}", e)))?;
+    let (pixels, _) = samples.as_chunks_mut::<N>();
+    transform.transform_in_place(pixels);
+    Ok(())
+}
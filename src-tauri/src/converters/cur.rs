@@ -1,56 +1,33 @@
-use image::ImageReader;
+use image::DynamicImage;
 use std::path::Path;
-use crate::models::ConversionError;
+use super::ico;
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions};
 
-pub fn convert_cur(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("cur", "CUR").options(&["ico", "cur"]).encodable().alpha();
+
+/// Cursors share the ICO container; sizes and entry format come from the
+/// `ico` options, the hotspot from `cur`.
+pub struct CurConverter;
+
+impl Converter for CurConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
+    fn decode(&self, input_path: &Path, _options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        ico::decode_largest(input_path)
+    }
 
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ico" => {
-            img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    fn decode_pages(&self, input_path: &Path, options: &ConversionOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+        if !options.ico.extract_all {
+            return Ok(vec![ico::decode_largest(input_path)?]);
+        }
+        ico::decode_entries(&std::fs::read(input_path)?)
     }
 
-    Ok(())
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let hotspot = (options.cur.hotspot_x, options.cur.hotspot_y);
+        ico::write_icon(img, &options.ico, Some(hotspot), output_path)
+    }
 }
@@ -1,50 +1,216 @@
-use image::ImageReader;
-use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_dcm(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
-    }
-
-    Ok(())
+use dicom_core::dictionary::DataDictionary;
+use dicom_core::header::Header;
+use dicom_core::{Tag, VR};
+use dicom_dictionary_std::{tags, StandardDataDictionary};
+use dicom_object::mem::InMemElement;
+use dicom_object::{DefaultDicomObject, InMemDicomObject};
+use dicom_pixeldata::{BitDepthOption, ConvertOptions, PixelDecoder, VoiLutOption, WindowLevel};
+use image::DynamicImage;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use super::registry::{Converter, FormatInfo};
+use super::utils;
+use crate::models::{ConversionError, ConversionOptions, DicomOptions};
+
+const INFO: FormatInfo = FormatInfo::image("dcm", "DICOM").aliases(&["dicom"]).options(&["dicom"]).sequence();
+
+/// Attributes that identify the patient, the people involved or the exact
+/// study, following the DICOM basic de-identification profile (PS3.15 E.1).
+/// Private attributes are dropped as well.
+const IDENTIFYING_TAGS: &[Tag] = &[
+    tags::PATIENT_NAME,
+    tags::PATIENT_ID,
+    tags::ISSUER_OF_PATIENT_ID,
+    tags::PATIENT_BIRTH_DATE,
+    tags::PATIENT_BIRTH_TIME,
+    tags::PATIENT_SEX,
+    tags::PATIENT_AGE,
+    tags::PATIENT_SIZE,
+    tags::PATIENT_WEIGHT,
+    tags::PATIENT_ADDRESS,
+    tags::PATIENT_TELEPHONE_NUMBERS,
+    tags::PATIENT_BIRTH_NAME,
+    tags::PATIENT_MOTHER_BIRTH_NAME,
+    Tag(0x0010, 0x1000), // Other Patient IDs (retired, still common in older files)
+    tags::OTHER_PATIENT_NAMES,
+    tags::OTHER_PATIENT_I_DS_SEQUENCE,
+    Tag(0x0010, 0x1090), // Medical Record Locator (retired)
+    tags::ETHNIC_GROUP,
+    tags::OCCUPATION,
+    tags::ADDITIONAL_PATIENT_HISTORY,
+    tags::PATIENT_COMMENTS,
+    tags::REFERRING_PHYSICIAN_NAME,
+    tags::PERFORMING_PHYSICIAN_NAME,
+    tags::PHYSICIANS_OF_RECORD,
+    tags::NAME_OF_PHYSICIANS_READING_STUDY,
+    tags::REQUESTING_PHYSICIAN,
+    tags::OPERATORS_NAME,
+    tags::INSTITUTION_NAME,
+    tags::INSTITUTION_ADDRESS,
+    tags::INSTITUTIONAL_DEPARTMENT_NAME,
+    tags::STATION_NAME,
+    tags::DEVICE_SERIAL_NUMBER,
+    tags::ACCESSION_NUMBER,
+    tags::STUDY_ID,
+    tags::STUDY_INSTANCE_UID,
+    tags::SERIES_INSTANCE_UID,
+    tags::SOP_INSTANCE_UID,
+    tags::FRAME_OF_REFERENCE_UID,
+    tags::STUDY_DATE,
+    tags::STUDY_TIME,
+    tags::SERIES_DATE,
+    tags::SERIES_TIME,
+    tags::ACQUISITION_DATE,
+    tags::ACQUISITION_TIME,
+    tags::ACQUISITION_DATE_TIME,
+    tags::CONTENT_DATE,
+    tags::CONTENT_TIME,
+];
+
+pub struct DicomConverter;
+
+impl Converter for DicomConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let mut frames = self.decode_pages(input_path, options)?;
+        Ok(frames.swap_remove(0))
+    }
+
+    /// Decodes the selected frames with the modality rescale and the VOI
+    /// window applied, so stored values map to displayable intensities.
+    fn decode_pages(&self, input_path: &Path, options: &ConversionOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+        let convert_options = convert_options(&options.dicom)?;
+        let object = open(input_path)?;
+        let pixels = object.decode_pixel_data().map_err(decode_error)?;
+
+        let count = pixels.number_of_frames() as usize;
+        let selected = match &options.dicom.frames {
+            Some(spec) => utils::parse_index_ranges(spec, count)?,
+            None => (0..count).collect(),
+        };
+        if selected.is_empty() {
+            return Err(ConversionError::ImageError("DICOM file has no frames to convert".to_string()));
+        }
+
+        selected
+            .into_iter()
+            .map(|frame| pixels.to_dynamic_image_with_options(frame as u32, &convert_options).map_err(decode_error))
+            .collect()
+    }
+
+    fn export_extras(&self, input_path: &Path, output_path: &Path, options: &ConversionOptions) -> Result<Vec<PathBuf>, ConversionError> {
+        if !options.dicom.export_metadata {
+            return Ok(Vec::new());
+        }
+
+        let object = open(input_path)?;
+        let attributes = attributes(&object, options.dicom.anonymize);
+
+        let stem = output_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let metadata_path = output_path.parent().unwrap_or(Path::new(".")).join(format!("{}_metadata.json", stem));
+        let json = serde_json::to_string_pretty(&attributes)
+            .map_err(|e| ConversionError::ConversionFailed(format!("Failed to write DICOM metadata: {}", e)))?;
+        std::fs::write(&metadata_path, json)?;
+
+        Ok(vec![metadata_path])
+    }
+
+    /// The override, else Recommended Display Frame Rate, Cine Rate or the
+    /// inverse of Frame Time (in milliseconds).
+    fn frame_rate(&self, input_path: &Path, options: &ConversionOptions) -> Option<f32> {
+        if let Some(rate) = options.dicom.frame_rate.filter(|r| *r > 0.0) {
+            return Some(rate);
+        }
+
+        let object = open(input_path).ok()?;
+        let number = |tag: Tag| {
+            object.element_opt(tag).ok().flatten().and_then(|e| e.to_float64().ok()).filter(|v| *v > 0.0)
+        };
+        number(tags::RECOMMENDED_DISPLAY_FRAME_RATE)
+            .or_else(|| number(tags::CINE_RATE))
+            .or_else(|| number(tags::FRAME_TIME).map(|ms| 1000.0 / ms))
+            .map(|rate| rate as f32)
+    }
+}
+
+fn open(input_path: &Path) -> Result<DefaultDicomObject, ConversionError> {
+    dicom_object::open_file(input_path).map_err(|e| ConversionError::ImageError(format!("Failed to read DICOM: {}", e)))
+}
+
+fn decode_error(e: dicom_pixeldata::Error) -> ConversionError {
+    ConversionError::ImageError(format!("Failed to decode DICOM pixel data: {}", e))
+}
+
+fn convert_options(options: &DicomOptions) -> Result<ConvertOptions, ConversionError> {
+    let voi_lut = match (options.window_center, options.window_width) {
+        (Some(center), Some(width)) if !(width > 0.0 && width.is_finite() && center.is_finite()) => {
+            return Err(ConversionError::ConversionFailed(format!(
+                "Invalid DICOM window: width must be greater than 0 (got center {}, width {})",
+                center, width
+            )));
+        }
+        (Some(center), Some(width)) => VoiLutOption::Custom(WindowLevel { center, width }),
+        _ => VoiLutOption::Default,
+    };
+    let bit_depth = if options.sixteen_bit { BitDepthOption::Force16Bit } else { BitDepthOption::Force8Bit };
+
+    Ok(ConvertOptions::new().with_voi_lut(voi_lut).with_bit_depth(bit_depth))
+}
+
+/// One data element in the exported metadata. Sequences nest their items.
+#[derive(Debug, Serialize)]
+struct Attribute {
+    tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'static str>,
+    vr: &'static str,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    value: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    items: Option<Vec<Vec<Attribute>>>,
+}
+
+fn attributes(object: &InMemDicomObject, anonymize: bool) -> Vec<Attribute> {
+    object
+        .iter()
+        .filter(|e| e.tag() != tags::PIXEL_DATA)
+        .filter(|e| !anonymize || !(e.tag().group() % 2 == 1 || IDENTIFYING_TAGS.contains(&e.tag())))
+        .map(|e| attribute(e, anonymize))
+        .collect()
+}
+
+fn attribute(element: &InMemElement, anonymize: bool) -> Attribute {
+    let tag = element.tag();
+    let vr = element.vr();
+
+    let (value, items) = match element.items() {
+        Some(items) => (Value::Null, Some(items.iter().map(|item| attributes(item, anonymize)).collect())),
+        None => (primitive_value(element, vr), None),
+    };
+
+    Attribute {
+        tag: format!("{:04X},{:04X}", tag.group(), tag.element()),
+        name: StandardDataDictionary.by_tag(tag).map(|entry| entry.alias),
+        vr: vr.to_string(),
+        value,
+        items,
+    }
+}
+
+/// Text and numbers as strings (an array when multi-valued); bulk binary
+/// values are left out.
+fn primitive_value(element: &InMemElement, vr: VR) -> Value {
+    if matches!(vr, VR::OB | VR::OD | VR::OF | VR::OL | VR::OV | VR::OW | VR::UN) {
+        return Value::Null;
+    }
+
+    match element.to_multi_str() {
+        Ok(values) if values.len() == 1 => Value::String(values[0].trim().to_string()),
+        Ok(values) => Value::Array(values.iter().map(|v| Value::String(v.trim().to_string())).collect()),
+        Err(_) => Value::Null,
+    }
 }
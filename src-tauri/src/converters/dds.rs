@@ -1,50 +1,128 @@
-use image::ImageReader;
+use image::{DynamicImage, GenericImage, GenericImageView};
+use image_dds::ddsfile::Dds;
+use image_dds::{ImageFormat, Mipmaps, Quality, Surface};
+use std::fs::File;
+use std::io::BufWriter;
 use std::path::Path;
-use crate::models::ConversionError;
+use super::registry::{Converter, FormatInfo};
+use super::tonemap;
+use crate::models::{ConversionError, ConversionOptions, DdsCompression, DdsFaceLayout, DdsOptions, DdsQuality};
 
-pub fn convert_dds(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("dds", "DDS").options(&["dds"]).encodable().alpha().hdr();
+
+pub struct DdsConverter;
+
+impl Converter for DdsConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let mut faces = self.decode_pages(input_path, options)?;
+        Ok(faces.swap_remove(0))
+    }
+
+    /// The base mip level of every array layer or cube face. Cube maps can
+    /// instead be laid out as a single horizontal cross.
+    fn decode_pages(&self, input_path: &Path, options: &ConversionOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+        let file = File::open(input_path)?;
+        let dds = Dds::read(file).map_err(|e| ConversionError::ImageError(format!("Failed to read DDS: {}", e)))?;
+        let surface = Surface::from_dds(&dds).map_err(decode_error)?;
+
+        let faces = if is_float(surface.image_format) {
+            let decoded = surface.decode_layers_mipmaps_rgbaf32(0..surface.layers, 0..1).map_err(decode_error)?;
+            (0..decoded.layers)
+                .filter_map(|layer| decoded.get_image(layer, 0, 0))
+                .map(DynamicImage::ImageRgba32F)
+                .collect::<Vec<_>>()
+        } else {
+            let decoded = surface.decode_layers_mipmaps_rgba8(0..surface.layers, 0..1).map_err(decode_error)?;
+            (0..decoded.layers)
+                .filter_map(|layer| decoded.get_image(layer, 0, 0))
+                .map(DynamicImage::ImageRgba8)
+                .collect::<Vec<_>>()
+        };
+
+        if faces.is_empty() {
+            return Err(ConversionError::ImageError("DDS file contains no image data".to_string()));
+        }
+        if faces.len() == 6 && options.dds.faces == DdsFaceLayout::Cross {
+            return Ok(vec![cube_cross(&faces)]);
+        }
+        Ok(faces)
+    }
+
+    /// Only the float encodings keep HDR values; block-compressed ones store 8 bits.
+    fn output_bit_depth(&self, options: &ConversionOptions) -> u8 {
+        if is_float(image_format(&options.dds)) { self.info().bit_depth } else { 8 }
+    }
+
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let format = image_format(&options.dds);
+        let quality = match options.dds.quality {
+            DdsQuality::Fast => Quality::Fast,
+            DdsQuality::Normal => Quality::Normal,
+            DdsQuality::Slow => Quality::Slow,
+        };
+        let mipmaps = if options.dds.mipmaps { Mipmaps::GeneratedAutomatic } else { Mipmaps::Disabled };
+
+        // DDS is registered as HDR-capable for BC6H; other encodings still need tone mapping.
+        let dds = if is_float(format) {
+            image_dds::dds_from_imagef32(&img.to_rgba32f(), format, quality, mipmaps)
+        } else {
+            let display = tonemap::to_display(img.clone(), &options.tone_map);
+            image_dds::dds_from_image(&display.to_rgba8(), format, quality, mipmaps)
+        }
+        .map_err(|e| ConversionError::ImageError(format!("Failed to encode DDS: {}", e)))?;
+
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        dds.write(&mut writer)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save DDS: {}", e)))
     }
+}
+
+fn decode_error(e: image_dds::error::SurfaceError) -> ConversionError {
+    ConversionError::ImageError(format!("Failed to decode DDS: {}", e))
+}
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+fn image_format(options: &DdsOptions) -> ImageFormat {
+    let srgb = options.srgb;
+    match options.compression {
+        DdsCompression::Bc1 if srgb => ImageFormat::BC1RgbaUnormSrgb,
+        DdsCompression::Bc1 => ImageFormat::BC1RgbaUnorm,
+        DdsCompression::Bc2 if srgb => ImageFormat::BC2RgbaUnormSrgb,
+        DdsCompression::Bc2 => ImageFormat::BC2RgbaUnorm,
+        DdsCompression::Bc3 if srgb => ImageFormat::BC3RgbaUnormSrgb,
+        DdsCompression::Bc3 => ImageFormat::BC3RgbaUnorm,
+        DdsCompression::Bc4 => ImageFormat::BC4RUnorm,
+        DdsCompression::Bc5 => ImageFormat::BC5RgUnorm,
+        DdsCompression::Bc6h => ImageFormat::BC6hRgbUfloat,
+        DdsCompression::Bc7 if srgb => ImageFormat::BC7RgbaUnormSrgb,
+        DdsCompression::Bc7 => ImageFormat::BC7RgbaUnorm,
+        DdsCompression::Uncompressed if srgb => ImageFormat::Rgba8UnormSrgb,
+        DdsCompression::Uncompressed => ImageFormat::Rgba8Unorm,
     }
+}
+
+fn is_float(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::BC6hRgbUfloat | ImageFormat::BC6hRgbSfloat | ImageFormat::Rgba16Float | ImageFormat::Rgba32Float
+    )
+}
 
-    Ok(())
+/// Lays six cube faces (+X, -X, +Y, -Y, +Z, -Z) out as a 4x3 horizontal cross.
+fn cube_cross(faces: &[DynamicImage]) -> DynamicImage {
+    let (size, _) = faces[0].dimensions();
+    let mut cross = match faces[0] {
+        DynamicImage::ImageRgba32F(_) => DynamicImage::new_rgba32f(size * 4, size * 3),
+        _ => DynamicImage::new_rgba8(size * 4, size * 3),
+    };
+
+    const CELLS: [(u32, u32); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+    for (face, (column, row)) in faces.iter().zip(CELLS) {
+        // Faces are always square in a valid cube map; copy_from only fails on overflow.
+        let _ = cross.copy_from(face, column * size, row * size);
+    }
+    cross
 }
@@ -0,0 +1,84 @@
+//! Bit depth tracking and reduction for targets that store fewer bits per
+//! sample than the source.
+
+use image::{DynamicImage, GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+use crate::models::BitDepthOptions;
+
+/// Depth reported for floating-point samples.
+pub const FLOAT_BITS: u8 = 32;
+
+/// 8x8 Bayer threshold matrix, values 0-63.
+pub const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Bits per sample held by the decoded image: 8, 16 or [`FLOAT_BITS`].
+pub fn sample_bits(img: &DynamicImage) -> u8 {
+    match img {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => FLOAT_BITS,
+        other if other.color().bytes_per_pixel() / other.color().channel_count() > 1 => 16,
+        _ => 8,
+    }
+}
+
+/// Reduces images deeper than 8 bits to 8 bits when that's all the target
+/// stores, keeping the channel layout. Targets with 10 bits or more take the
+/// image as it is and scale samples themselves.
+pub fn reduce(img: DynamicImage, target_bits: u8, options: &BitDepthOptions) -> DynamicImage {
+    if target_bits > 8 || sample_bits(&img) <= 8 {
+        return img;
+    }
+
+    let color = img.color();
+    let (width, height) = (img.width(), img.height());
+    let channels = color.channel_count() as usize;
+    let samples = match (color.has_color(), color.has_alpha()) {
+        (false, false) => img.to_luma16().into_raw(),
+        (false, true) => img.to_luma_alpha16().into_raw(),
+        (true, false) => img.to_rgb16().into_raw(),
+        (true, true) => img.to_rgba16().into_raw(),
+    };
+
+    // Alpha is rounded rather than dithered, so edges don't pick up noise.
+    let data: Vec<u8> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| {
+            let (pixel, channel) = (i / channels, i % channels);
+            let is_alpha = color.has_alpha() && channel == channels - 1;
+            let offset = if options.dither && !is_alpha {
+                let (x, y) = (pixel % width as usize, pixel / width as usize);
+                (BAYER[y % 8][x % 8] as f32 + 0.5) / 64.0
+            } else {
+                0.5
+            };
+            (v as f32 * 255.0 / 65535.0 + offset).floor().min(255.0) as u8
+        })
+        .collect();
+
+    let reduced = match (color.has_color(), color.has_alpha()) {
+        (false, false) => GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8),
+        (false, true) => GrayAlphaImage::from_raw(width, height, data).map(DynamicImage::ImageLumaA8),
+        (true, false) => RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+        (true, true) => RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+    };
+    reduced.unwrap_or(img)
+}
+
+/// Describes the precision lost writing `source_bits` samples to a target
+/// storing `target_bits`, if any was.
+pub fn loss_note(source_bits: u8, target_bits: u8, options: &BitDepthOptions) -> Option<String> {
+    if source_bits <= target_bits {
+        return None;
+    }
+    let source = if source_bits == FLOAT_BITS { "32-bit float".to_string() } else { format!("{}-bit", source_bits) };
+    let dithered = if options.dither && target_bits == 8 { " with dithering" } else { "" };
+    Some(format!("{} samples reduced to {}-bit{}", source, target_bits, dithered))
+}
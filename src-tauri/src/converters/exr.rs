@@ -1,50 +1,161 @@
-use image::ImageReader;
+use exr::prelude::{AnyChannel, AnyChannels, FlatSamples, Layer};
+use image::{DynamicImage, ImageFormat, Rgb32FImage, Rgba32FImage};
 use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_exr(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
-    }
-
-    Ok(())
+use super::registry::{Converter, FormatInfo};
+use super::tonemap;
+use crate::models::{ConversionError, ConversionOptions, ExrOptions};
+
+const INFO: FormatInfo = FormatInfo::image("exr", "OpenEXR").options(&["exr", "toneMap"]).encodable().alpha().hdr();
+
+type FlatLayer = Layer<AnyChannels<FlatSamples>>;
+
+pub struct ExrConverter;
+
+impl Converter for ExrConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    /// Reads one layer of a (possibly multi-part, multi-layer) file as linear
+    /// floating-point RGB(A). Tone mapping happens later, only if the target
+    /// cannot hold HDR values.
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let image = exr::prelude::read_all_flat_layers_from_file(input_path)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to decode OpenEXR: {}", e)))?;
+
+        let (layer, prefix) = select_layer(&image.layer_data, &options.exr)?;
+        let channels = select_channels(layer, &prefix, &options.exr)?;
+
+        let (width, height) = (layer.size.width() as u32, layer.size.height() as u32);
+        let pixel_count = layer.size.area();
+        let values: Vec<Vec<f32>> = channels.iter().map(|c| c.sample_data.values_as_f32().collect()).collect();
+        let malformed = || ConversionError::ImageError("OpenEXR channel data has an unexpected size".to_string());
+
+        if values.iter().any(|v| v.len() != pixel_count) {
+            return Err(malformed());
+        }
+
+        // One channel is shown as grey, two as grey plus alpha, four as RGBA.
+        let rgb = if values.len() < 3 { [0, 0, 0] } else { [0, 1, 2] };
+        let alpha = match values.len() {
+            2 => Some(1),
+            4 => Some(3),
+            _ => None,
+        };
+
+        match alpha {
+            None => {
+                let data = (0..pixel_count).flat_map(|p| rgb.map(|c| values[c][p])).collect();
+                Rgb32FImage::from_raw(width, height, data).map(DynamicImage::ImageRgb32F).ok_or_else(malformed)
+            }
+            Some(a) => {
+                let data = (0..pixel_count)
+                    .flat_map(|p| [values[rgb[0]][p], values[rgb[1]][p], values[rgb[2]][p], values[a][p]])
+                    .collect();
+                Rgba32FImage::from_raw(width, height, data).map(DynamicImage::ImageRgba32F).ok_or_else(malformed)
+            }
+        }
+    }
+
+    /// Float sources are written as-is; integer sources are treated as sRGB
+    /// and linearized, since EXR stores scene-linear values.
+    fn encode(&self, img: &DynamicImage, output_path: &Path, _options: &ConversionOptions) -> Result<(), ConversionError> {
+        let is_float = matches!(img, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+
+        let linear = if img.color().has_alpha() {
+            let mut buffer = img.to_rgba32f();
+            if !is_float {
+                buffer.pixels_mut().for_each(|p| p.0[..3].iter_mut().for_each(|v| *v = tonemap::srgb_to_linear(*v)));
+            }
+            DynamicImage::ImageRgba32F(buffer)
+        } else {
+            let mut buffer = img.to_rgb32f();
+            if !is_float {
+                buffer.pixels_mut().for_each(|p| p.0.iter_mut().for_each(|v| *v = tonemap::srgb_to_linear(*v)));
+            }
+            DynamicImage::ImageRgb32F(buffer)
+        };
+
+        linear.save_with_format(output_path, ImageFormat::OpenExr)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save OpenEXR: {}", e)))
+    }
+}
+
+/// Finds the layer named by `exr.layer`, either as a part name or as the
+/// `layer.` prefix of channel names within a part. Without a name, the first
+/// part is used, preferring its unprefixed channels.
+fn select_layer<'a>(layers: &'a [FlatLayer], options: &ExrOptions) -> Result<(&'a FlatLayer, String), ConversionError> {
+    let first = layers.first().ok_or_else(|| ConversionError::ImageError("OpenEXR file has no layers".to_string()))?;
+
+    let Some(name) = options.layer.as_deref().filter(|n| !n.is_empty()) else {
+        let prefix = if first.channel_data.list.iter().any(|c| !c.name.to_string().contains('.')) {
+            String::new()
+        } else {
+            first.channel_data.list.first().map(|c| channel_prefix(&c.name.to_string())).unwrap_or_default()
+        };
+        return Ok((first, prefix));
+    };
+
+    if let Some(layer) = layers.iter().find(|l| l.attributes.layer_name.as_ref().is_some_and(|n| n.eq(name))) {
+        return Ok((layer, String::new()));
+    }
+
+    let prefix = format!("{}.", name);
+    layers
+        .iter()
+        .find(|l| l.channel_data.list.iter().any(|c| c.name.to_string().starts_with(&prefix)))
+        .map(|layer| (layer, prefix))
+        .ok_or_else(|| ConversionError::ConversionFailed(format!("OpenEXR layer '{}' not found; available: {}", name, layer_names(layers).join(", "))))
+}
+
+/// Picks the channels to show, in output order. Explicit `exr.channels` are
+/// looked up relative to the layer; otherwise RGB(A), then Y(A), then the
+/// first channel is used.
+fn select_channels<'a>(layer: &'a FlatLayer, prefix: &str, options: &ExrOptions) -> Result<Vec<&'a AnyChannel<FlatSamples>>, ConversionError> {
+    let find = |name: &str| layer.channel_data.list.iter().find(|c| c.name.eq(&format!("{}{}", prefix, name)));
+
+    if let Some(spec) = options.channels.as_deref().filter(|s| !s.trim().is_empty()) {
+        let names: Vec<&str> = spec.split(',').map(str::trim).filter(|n| !n.is_empty()).collect();
+        if names.len() > 4 {
+            return Err(ConversionError::ConversionFailed("At most four OpenEXR channels can be selected".to_string()));
+        }
+        return names
+            .iter()
+            .map(|name| find(name).ok_or_else(|| ConversionError::ConversionFailed(format!("OpenEXR channel '{}{}' not found", prefix, name))))
+            .collect();
+    }
+
+    let alpha = find("A");
+    if let (Some(r), Some(g), Some(b)) = (find("R"), find("G"), find("B")) {
+        return Ok([Some(r), Some(g), Some(b), alpha].into_iter().flatten().collect());
+    }
+    if let Some(y) = find("Y") {
+        return Ok([Some(y), alpha].into_iter().flatten().collect());
+    }
+    layer.channel_data.list
+        .iter()
+        .find(|c| c.name.to_string().starts_with(prefix))
+        .map(|c| vec![c])
+        .ok_or_else(|| ConversionError::ImageError("OpenEXR layer has no channels".to_string()))
+}
+
+fn channel_prefix(name: &str) -> String {
+    name.rfind('.').map(|i| name[..=i].to_string()).unwrap_or_default()
+}
+
+fn layer_names(layers: &[FlatLayer]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for layer in layers {
+        if let Some(name) = &layer.attributes.layer_name {
+            names.push(name.to_string());
+        }
+        for channel in &layer.channel_data.list {
+            let prefix = channel_prefix(&channel.name.to_string());
+            let prefix = prefix.trim_end_matches('.');
+            if !prefix.is_empty() && !names.iter().any(|n| n == prefix) {
+                names.push(prefix.to_string());
+            }
+        }
+    }
+    names
 }
@@ -1,50 +1,396 @@
-use image::ImageReader;
-use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_fits(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
-    }
-
-    Ok(())
+use image::{DynamicImage, ImageBuffer, Luma};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions, FitsOptions, FitsStretch};
+
+const INFO: FormatInfo = FormatInfo::image("fits", "FITS").aliases(&["fit", "fts"]).options(&["fits"]);
+
+const BLOCK_SIZE: usize = 2880;
+const CARD_SIZE: usize = 80;
+
+/// Samples used to estimate percentiles on large images.
+const PERCENTILE_SAMPLES: usize = 100_000;
+const ZSCALE_SAMPLES: usize = 1000;
+const ZSCALE_CONTRAST: f64 = 0.25;
+
+pub struct FitsConverter;
+
+impl Converter for FitsConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    /// Reads one plane of the selected image HDU as greyscale. FITS stores the
+    /// first row at the bottom, so rows are flipped to the usual orientation.
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let data = std::fs::read(input_path)?;
+        let hdus = parse_hdus(&data)?;
+        let hdu = select_hdu(&hdus, &options.fits)?;
+
+        let values = hdu.plane(&data, options.fits.plane)?;
+        let levels = stretch(&values, &options.fits);
+        let (width, height) = (hdu.axes[0] as u32, hdu.axes[1] as u32);
+        let malformed_size = || malformed("image data has an unexpected size");
+
+        let flipped = levels.chunks(width as usize).rev().flatten().copied();
+        if options.fits.sixteen_bit {
+            let samples = flipped.map(|t| (t * 65535.0).round() as u16).collect();
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, samples).map(DynamicImage::ImageLuma16).ok_or_else(malformed_size)
+        } else {
+            let samples = flipped.map(|t| (t * 255.0).round() as u8).collect();
+            ImageBuffer::<Luma<u8>, _>::from_raw(width, height, samples).map(DynamicImage::ImageLuma8).ok_or_else(malformed_size)
+        }
+    }
+
+    fn export_extras(&self, input_path: &Path, output_path: &Path, options: &ConversionOptions) -> Result<Vec<PathBuf>, ConversionError> {
+        if !options.fits.export_header {
+            return Ok(Vec::new());
+        }
+
+        let data = std::fs::read(input_path)?;
+        let hdus = parse_hdus(&data)?;
+        let hdu = select_hdu(&hdus, &options.fits)?;
+
+        let stem = output_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let header_path = output_path.parent().unwrap_or(Path::new(".")).join(format!("{}_header.json", stem));
+        let json = serde_json::to_string_pretty(&hdu.cards)
+            .map_err(|e| ConversionError::ConversionFailed(format!("Failed to write FITS header: {}", e)))?;
+        std::fs::write(&header_path, json)?;
+
+        Ok(vec![header_path])
+    }
+}
+
+fn malformed(what: &str) -> ConversionError {
+    ConversionError::ImageError(format!("Malformed FITS: {}", what))
+}
+
+/// One header card. Commentary cards (`COMMENT`, `HISTORY`, blank) carry
+/// their text as the value.
+#[derive(Debug, Serialize)]
+struct Card {
+    keyword: String,
+    value: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+struct Hdu {
+    index: usize,
+    cards: Vec<Card>,
+    is_image: bool,
+    bitpix: i64,
+    axes: Vec<usize>,
+    data_offset: usize,
+    bzero: f64,
+    bscale: f64,
+    blank: Option<i64>,
+}
+
+impl Hdu {
+    fn keyword(&self, name: &str) -> Option<&Value> {
+        self.cards.iter().find(|c| c.keyword == name).map(|c| &c.value)
+    }
+
+    fn has_image_data(&self) -> bool {
+        self.is_image && self.axes.len() >= 2 && self.axes.iter().all(|&n| n > 0)
+    }
+
+    /// Physical values (`BZERO + BSCALE * raw`) of one 2D plane; blank and
+    /// undefined samples become NaN.
+    fn plane(&self, data: &[u8], plane: usize) -> Result<Vec<f64>, ConversionError> {
+        let too_large = || malformed("data unit is too large");
+        let planes = checked_product(&self.axes[2..]).ok_or_else(too_large)?;
+        if plane >= planes {
+            return Err(ConversionError::ConversionFailed(format!("FITS plane {} requested but HDU {} has {}", plane, self.index, planes)));
+        }
+
+        let sample_size = (self.bitpix.unsigned_abs() / 8) as usize;
+        let plane_bytes = checked_product(&[self.axes[0], self.axes[1], sample_size]).ok_or_else(too_large)?;
+        let start = plane.checked_mul(plane_bytes).and_then(|offset| offset.checked_add(self.data_offset)).ok_or_else(too_large)?;
+        let end = start.checked_add(plane_bytes).ok_or_else(too_large)?;
+        let bytes = data.get(start..end).ok_or_else(|| malformed("data unit is truncated"))?;
+
+        let scale = |raw: i64| {
+            if self.blank == Some(raw) { f64::NAN } else { self.bzero + self.bscale * raw as f64 }
+        };
+        let values = match self.bitpix {
+            8 => bytes.iter().map(|&b| scale(b as i64)).collect(),
+            16 => bytes.chunks_exact(2).map(|c| scale(i16::from_be_bytes([c[0], c[1]]) as i64)).collect(),
+            32 => bytes.chunks_exact(4).map(|c| scale(i32::from_be_bytes([c[0], c[1], c[2], c[3]]) as i64)).collect(),
+            64 => bytes.chunks_exact(8).map(|c| scale(i64::from_be_bytes(c.try_into().unwrap_or_default()))).collect(),
+            -32 => bytes
+                .chunks_exact(4)
+                .map(|c| self.bzero + self.bscale * f32::from_be_bytes([c[0], c[1], c[2], c[3]]) as f64)
+                .collect(),
+            -64 => bytes
+                .chunks_exact(8)
+                .map(|c| self.bzero + self.bscale * f64::from_be_bytes(c.try_into().unwrap_or_default()))
+                .collect(),
+            other => return Err(malformed(&format!("unsupported BITPIX {}", other))),
+        };
+        Ok(values)
+    }
+}
+
+/// Walks every header and data unit in the file.
+fn parse_hdus(data: &[u8]) -> Result<Vec<Hdu>, ConversionError> {
+    let mut hdus = Vec::new();
+    let mut pos = 0;
+
+    while data.len().saturating_sub(pos) >= BLOCK_SIZE {
+        let (cards, header_len) = match parse_header(&data[pos..]) {
+            Ok(header) => header,
+            // Trailing blocks that aren't a header (e.g. padding) end the file.
+            Err(_) if !hdus.is_empty() => break,
+            Err(e) => return Err(e),
+        };
+
+        let int = |name: &str| cards.iter().find(|c| c.keyword == name).and_then(|c| c.value.as_i64());
+        let float = |name: &str| cards.iter().find(|c| c.keyword == name).and_then(|c| c.value.as_f64());
+
+        let bitpix = int("BITPIX").ok_or_else(|| malformed("missing BITPIX"))?;
+        let naxis = int("NAXIS").ok_or_else(|| malformed("missing NAXIS"))?;
+        let axes = (1..=naxis)
+            .map(|n| int(&format!("NAXIS{}", n)).map(|v| v.max(0) as usize).ok_or_else(|| malformed(&format!("missing NAXIS{}", n))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let xtension = cards.iter().find(|c| c.keyword == "XTENSION").and_then(|c| c.value.as_str()).map(str::trim);
+        let is_image = match xtension {
+            None => hdus.is_empty(),
+            Some(kind) => kind == "IMAGE" || kind == "IUEIMAGE",
+        };
+
+        let pcount = int("PCOUNT").unwrap_or(0).max(0) as usize;
+        let gcount = int("GCOUNT").unwrap_or(1).max(1) as usize;
+        let elements = if axes.is_empty() { Some(0) } else { checked_product(&axes) };
+        let data_len = elements
+            .and_then(|elements| elements.checked_add(pcount))
+            .and_then(|samples| checked_product(&[(bitpix.unsigned_abs() / 8) as usize, gcount, samples]))
+            .ok_or_else(|| malformed("data unit is too large"))?;
+
+        let data_offset = pos + header_len;
+        hdus.push(Hdu {
+            index: hdus.len(),
+            is_image,
+            bitpix,
+            axes,
+            data_offset,
+            bzero: float("BZERO").unwrap_or(0.0),
+            bscale: float("BSCALE").unwrap_or(1.0),
+            blank: int("BLANK"),
+            cards,
+        });
+
+        pos = data_len
+            .checked_next_multiple_of(BLOCK_SIZE)
+            .and_then(|padded| padded.checked_add(data_offset))
+            .ok_or_else(|| malformed("data unit is too large"))?;
+    }
+
+    if hdus.is_empty() {
+        return Err(malformed("no header found"));
+    }
+    Ok(hdus)
+}
+
+/// Product of header dimensions, or `None` if it overflows.
+fn checked_product(values: &[usize]) -> Option<usize> {
+    values.iter().try_fold(1usize, |product, &n| product.checked_mul(n))
+}
+
+/// Reads 80-character cards until `END`, returning them with the padded
+/// header length.
+fn parse_header(data: &[u8]) -> Result<(Vec<Card>, usize), ConversionError> {
+    let mut cards = Vec::new();
+
+    for (index, raw) in data.chunks_exact(CARD_SIZE).enumerate() {
+        if !raw.iter().all(|b| (0x20..=0x7E).contains(b)) {
+            return Err(malformed("header contains non-ASCII text"));
+        }
+        let text = String::from_utf8_lossy(raw);
+        let keyword = text[..8].trim_end().to_string();
+
+        if keyword == "END" {
+            let header_len = (index + 1) * CARD_SIZE;
+            return Ok((cards, header_len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE));
+        }
+
+        if &text[8..10] == "= " {
+            let (value, comment) = parse_value(&text[10..]);
+            cards.push(Card { keyword, value, comment });
+        } else if !keyword.is_empty() || !text[8..].trim().is_empty() {
+            cards.push(Card { keyword, value: Value::String(text[8..].trim_end().to_string()), comment: None });
+        }
+    }
+
+    Err(malformed("header has no END card"))
+}
+
+fn parse_value(field: &str) -> (Value, Option<String>) {
+    let field = field.trim_start();
+    let comment_of = |rest: &str| rest.split_once('/').map(|(_, c)| c.trim().to_string()).filter(|c| !c.is_empty());
+
+    if let Some(quoted) = field.strip_prefix('\'') {
+        // Quotes inside strings are doubled; trailing spaces are not significant.
+        let mut text = String::new();
+        let mut chars = quoted.char_indices().peekable();
+        let mut rest = "";
+        while let Some((i, c)) = chars.next() {
+            if c == '\'' {
+                if chars.peek().is_some_and(|&(_, next)| next == '\'') {
+                    chars.next();
+                    text.push('\'');
+                    continue;
+                }
+                rest = &quoted[i + 1..];
+                break;
+            }
+            text.push(c);
+        }
+        return (Value::String(text.trim_end().to_string()), comment_of(rest));
+    }
+
+    let (value, comment) = match field.split_once('/') {
+        Some((value, comment)) => (value.trim(), Some(comment.trim().to_string()).filter(|c| !c.is_empty())),
+        None => (field.trim(), None),
+    };
+    let value = match value {
+        "T" => Value::Bool(true),
+        "F" => Value::Bool(false),
+        "" => Value::Null,
+        _ => value
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| value.replace(['D', 'd'], "E").parse::<f64>().map(Value::from))
+            .unwrap_or_else(|_| Value::String(value.to_string())),
+    };
+    (value, comment)
+}
+
+/// Finds the HDU named by `fits.hdu`, as an index or an `EXTNAME`, or the
+/// first one that holds an image.
+fn select_hdu<'a>(hdus: &'a [Hdu], options: &FitsOptions) -> Result<&'a Hdu, ConversionError> {
+    let Some(name) = options.hdu.as_deref().map(str::trim).filter(|n| !n.is_empty()) else {
+        return hdus
+            .iter()
+            .find(|h| h.has_image_data())
+            .ok_or_else(|| ConversionError::ConversionFailed("FITS file contains no image HDU".to_string()));
+    };
+
+    let hdu = match name.parse::<usize>() {
+        Ok(index) => hdus.get(index),
+        Err(_) => hdus.iter().find(|h| h.keyword("EXTNAME").and_then(Value::as_str).is_some_and(|n| n.trim().eq_ignore_ascii_case(name))),
+    }
+    .ok_or_else(|| ConversionError::ConversionFailed(format!("FITS HDU '{}' not found; the file has {} HDUs", name, hdus.len())))?;
+
+    if !hdu.has_image_data() {
+        return Err(ConversionError::ConversionFailed(format!("FITS HDU '{}' does not contain a 2D image", name)));
+    }
+    Ok(hdu)
+}
+
+/// Maps physical values to 0.0-1.0 display levels: clip to the percentile or
+/// zscale limits, then apply the stretch curve. Undefined pixels are black.
+fn stretch(values: &[f64], options: &FitsOptions) -> Vec<f64> {
+    let mut sample: Vec<f64> = strided(values.iter().copied().filter(|v| v.is_finite()), values.len(), PERCENTILE_SAMPLES);
+    if sample.is_empty() {
+        return vec![0.0; values.len()];
+    }
+    sample.sort_by(f64::total_cmp);
+
+    let (low, high) = match options.stretch {
+        FitsStretch::Zscale => {
+            let zsample = strided(sample.iter().copied(), sample.len(), ZSCALE_SAMPLES);
+            zscale(&zsample)
+        }
+        _ => {
+            let low = options.low_percentile.clamp(0.0, 100.0) as f64;
+            let high = options.high_percentile.clamp(0.0, 100.0) as f64;
+            (percentile(&sample, low.min(high)), percentile(&sample, low.max(high)))
+        }
+    };
+    let range = high - low;
+
+    let curve = |t: f64| match options.stretch {
+        FitsStretch::Linear | FitsStretch::Zscale => t,
+        FitsStretch::Log => (1000.0 * t).ln_1p() / 1000f64.ln_1p(),
+        FitsStretch::Sqrt => t.sqrt(),
+        FitsStretch::Asinh => (10.0 * t).asinh() / 10f64.asinh(),
+    };
+
+    values
+        .iter()
+        .map(|&v| {
+            if !v.is_finite() || range <= 0.0 {
+                return 0.0;
+            }
+            curve(((v - low) / range).clamp(0.0, 1.0))
+        })
+        .collect()
+}
+
+/// Takes at most `limit` evenly spaced items from an iterator of roughly `len` items.
+fn strided(items: impl Iterator<Item = f64>, len: usize, limit: usize) -> Vec<f64> {
+    let step = len.div_ceil(limit).max(1);
+    items.step_by(step).collect()
+}
+
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let index = (percent / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+/// IRAF's zscale: fit a line to the sorted sample with iterative outlier
+/// rejection and scale its slope by the contrast around the median.
+fn zscale(sorted: &[f64]) -> (f64, f64) {
+    const MAX_ITERATIONS: usize = 5;
+    const REJECT_SIGMA: f64 = 2.5;
+
+    let n = sorted.len();
+    let (min, max) = (sorted[0], sorted[n - 1]);
+    let center = (n - 1) / 2;
+    let median = if n % 2 == 1 { sorted[center] } else { (sorted[center] + sorted[center + 1]) / 2.0 };
+    let min_good = (n / 2).max(5);
+
+    let mut good = vec![true; n];
+    let mut slope = 0.0;
+    for _ in 0..MAX_ITERATIONS {
+        let points: Vec<(f64, f64)> = (0..n).filter(|&i| good[i]).map(|i| (i as f64, sorted[i])).collect();
+        if points.len() < min_good {
+            return (min, max);
+        }
+
+        let count = points.len() as f64;
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / count;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / count;
+        let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+        let intercept = mean_y - slope * mean_x;
+
+        let residual = |i: usize| sorted[i] - (intercept + slope * i as f64);
+        let sigma = (points.iter().map(|p| residual(p.0 as usize).powi(2)).sum::<f64>() / count).sqrt();
+
+        let mut rejected = 0;
+        for (i, keep) in good.iter_mut().enumerate() {
+            if *keep && residual(i).abs() > REJECT_SIGMA * sigma {
+                *keep = false;
+                rejected += 1;
+            }
+        }
+        if rejected == 0 {
+            break;
+        }
+    }
+
+    if good.iter().filter(|&&g| g).count() < min_good {
+        return (min, max);
+    }
+    let slope = slope / ZSCALE_CONTRAST;
+    let low = (median - center as f64 * slope).max(min);
+    let high = (median + (n - 1 - center) as f64 * slope).min(max);
+    (low, high)
 }
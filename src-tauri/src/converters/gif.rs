@@ -1,59 +1,129 @@
-use image::ImageReader;
+use gif::{DisposalMethod, Encoder, Frame, Repeat};
+use image::codecs::gif::GifDecoder;
+use image::DynamicImage;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
 use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_gif(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ico" => {
-            img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+use std::time::Duration;
+use super::animation::{self, Animation, AnimationFrame};
+use super::quantize::{self, Palette};
+use super::registry::{self, Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions, GifOptions, GifPalette};
+
+const INFO: FormatInfo = FormatInfo::image("gif", "GIF").options(&["gif"]).encodable().alpha().animated();
+
+/// Application extension that carries the loop count.
+const NETSCAPE_LOOP: &[u8] = b"NETSCAPE2.0";
+
+pub struct GifConverter;
+
+impl Converter for GifConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        registry::read_image(input_path, options)
+    }
+
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let frame = AnimationFrame { image: img.to_rgba8(), delay: Duration::ZERO };
+        write(std::slice::from_ref(&frame), 1, output_path, &options.gif)
     }
 
+    fn decode_animation(&self, input_path: &Path, _options: &ConversionOptions) -> Result<Option<Animation>, ConversionError> {
+        let data = std::fs::read(input_path)?;
+        let decoder = GifDecoder::new(Cursor::new(&data))
+            .map_err(|e| ConversionError::ImageError(format!("Failed to read GIF: {}", e)))?;
+        animation::decode(decoder, loop_count(&data)).map(Some)
+    }
+
+    /// GIF delays are in hundredths of a second, so timing is rounded to that.
+    fn encode_animation(&self, animation: &Animation, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        write(&animation.frames, animation.loop_count, output_path, &options.gif)
+    }
+}
+
+/// Quantizes and writes full-canvas frames. Each frame clears to the
+/// background before the next, so transparent areas don't show through.
+fn write(frames: &[AnimationFrame], loop_count: u32, output_path: &Path, options: &GifOptions) -> Result<(), ConversionError> {
+    let save_error = |e: gif::EncodingError| ConversionError::ImageError(format!("Failed to save GIF: {}", e));
+    let (width, height) = frames.first().map_or((0, 0), |frame| frame.image.dimensions());
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(ConversionError::ConversionFailed("GIF images can be at most 65535 pixels wide and high".to_string()));
+    };
+
+    let colors = options.colors.clamp(2, 256) as usize;
+    let threshold = options.alpha_threshold;
+    let has_transparency = |frames: &[AnimationFrame]| frames.iter().any(|f| f.image.pixels().any(|p| p[3] < threshold));
+    // The transparent entry takes one of the palette slots.
+    let fit = |frames: &[AnimationFrame]| {
+        let transparent = has_transparency(frames);
+        let images: Vec<_> = frames.iter().map(|frame| &frame.image).collect();
+        let palette = Palette::build(&images, colors - transparent as usize, threshold);
+        let transparent = transparent.then_some(palette.colors().len() as u8);
+        (palette, transparent)
+    };
+    let palette_bytes = |palette: &Palette, transparent: Option<u8>| {
+        let mut bytes: Vec<u8> = palette.colors().iter().flatten().copied().collect();
+        if transparent.is_some() {
+            bytes.extend_from_slice(&[0, 0, 0]);
+        }
+        bytes
+    };
+
+    let global = (options.palette == GifPalette::Global).then(|| fit(frames));
+    let global_bytes = global.as_ref().map_or_else(Vec::new, |(palette, transparent)| palette_bytes(palette, *transparent));
+    let mut encoder = Encoder::new(BufWriter::new(File::create(output_path)?), width, height, &global_bytes).map_err(save_error)?;
+
+    // The stored count is repeats after the first play, and a file without
+    // one plays once.
+    match loop_count {
+        0 => encoder.set_repeat(Repeat::Infinite).map_err(save_error)?,
+        1 => {}
+        plays => encoder.set_repeat(Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16)).map_err(save_error)?,
+    }
+
+    for frame in frames {
+        let local;
+        let (palette, transparent) = match &global {
+            Some((palette, transparent)) => (palette, *transparent),
+            None => {
+                local = fit(std::slice::from_ref(frame));
+                (&local.0, local.1)
+            }
+        };
+        let indices = quantize::index(&frame.image, palette, transparent, threshold, options.dither);
+
+        encoder
+            .write_frame(&Frame {
+                width,
+                height,
+                delay: ((frame.delay.as_micros() + 5_000) / 10_000).min(u16::MAX as u128) as u16,
+                dispose: DisposalMethod::Background,
+                transparent,
+                palette: global.is_none().then(|| palette_bytes(palette, transparent)),
+                buffer: Cow::Owned(indices),
+                ..Frame::default()
+            })
+            .map_err(save_error)?;
+    }
     Ok(())
 }
 
-pub fn convert_to_gif(input_path: &str, output_path: &str) -> Result<(), ConversionError> {
-    convert_gif(input_path, output_path, "gif")}
\ No newline at end of file
+/// Times the file asks to be played, from its NETSCAPE2.0 extension:
+/// 0 is forever, and files without one play once.
+fn loop_count(data: &[u8]) -> u32 {
+    let Some(at) = data.windows(NETSCAPE_LOOP.len()).position(|w| w == NETSCAPE_LOOP) else {
+        return 1;
+    };
+    match data.get(at + NETSCAPE_LOOP.len()..at + NETSCAPE_LOOP.len() + 4) {
+        Some(&[3, 1, low, high]) => match u16::from_le_bytes([low, high]) {
+            0 => 0,
+            repeats => repeats as u32 + 1,
+        },
+        _ => 1,
+    }
+}
+
@@ -1,48 +1,43 @@
-use image::ImageReader;
+use image::DynamicImage;
+use libheif_rs::LibHeif;
 use std::path::Path;
-use crate::models::ConversionError;
+use super::heif;
+use super::metadata::Metadata;
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions};
 
-pub fn convert_heic(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("heic", "HEIC").aliases(&["heif", "hif"]).options(&["heic"]);
+
+pub struct HeicConverter;
+
+impl Converter for HeicConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
+    fn metadata(&self, input_path: &Path) -> Metadata {
+        heif::read_metadata(input_path)
+    }
 
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        heif::decode_primary(input_path, options)
     }
 
-    Ok(())
+    /// The primary image first, followed by any other top-level images (burst
+    /// shots, Live Photo stills) when `heic.includeSecondary` is set.
+    fn decode_pages(&self, input_path: &Path, options: &ConversionOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+        let ctx = heif::open(input_path)?;
+        let lib_heif = LibHeif::new();
+        let primary = ctx.primary_image_handle().map_err(heif::decode_error)?;
+
+        let mut images = vec![heif::decode_handle(&lib_heif, &primary, options)?];
+        if options.heic.include_secondary {
+            for handle in ctx.top_level_image_handles() {
+                if handle.item_id() != primary.item_id() {
+                    images.push(heif::decode_handle(&lib_heif, &handle, options)?);
+                }
+            }
+        }
+        Ok(images)
+    }
 }
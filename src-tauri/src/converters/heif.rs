@@ -0,0 +1,142 @@
+//! libheif plumbing shared by the HEIF-family formats (HEIC and AVIF).
+
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba};
+use libheif_rs::{color_profile_types, Channel, ColorProfile, ColorSpace, DecodingOptions, HeifContext, HeifError, Image, ImageHandle, LibHeif, RgbChroma};
+use std::path::Path;
+use super::metadata::Metadata;
+use crate::models::{ConversionError, ConversionOptions};
+
+pub fn decode_error(e: HeifError) -> ConversionError {
+    ConversionError::ImageError(format!("Failed to decode HEIF image: {}", e))
+}
+
+pub fn open(input_path: &Path) -> Result<HeifContext<'static>, ConversionError> {
+    HeifContext::read_from_file(&input_path.to_string_lossy()).map_err(decode_error)
+}
+
+pub fn decode_primary(input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+    let ctx = open(input_path)?;
+    let handle = ctx.primary_image_handle().map_err(decode_error)?;
+    decode_handle(&LibHeif::new(), &handle, options)
+}
+
+/// EXIF, XMP and the ICC profile attached to the primary image.
+pub fn read_metadata(input_path: &Path) -> Metadata {
+    let Ok(ctx) = open(input_path) else { return Metadata::default() };
+    let Ok(handle) = ctx.primary_image_handle() else { return Metadata::default() };
+
+    let mut metadata = Metadata {
+        icc: handle
+            .color_profile_raw()
+            .filter(|profile| [color_profile_types::R_ICC, color_profile_types::PROF].contains(&profile.profile_type()))
+            .map(|profile| profile.data),
+        ..Metadata::default()
+    };
+    for block in handle.all_metadata() {
+        if block.item_type.0 == *b"Exif" {
+            // The block starts with the offset of the TIFF header that follows.
+            let offset = block.raw_data.get(..4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+            metadata.exif = offset.and_then(|offset| block.raw_data.get(4 + offset..)).map(<[u8]>::to_vec);
+        } else if block.content_type == "application/rdf+xml" {
+            metadata.xmp = Some(block.raw_data);
+        }
+    }
+    metadata
+}
+
+/// Decodes one image item. libheif assembles grid tiles, merges the alpha
+/// auxiliary image and applies the container's rotation/mirror properties,
+/// which is where HEIF records camera orientation; the EXIF Orientation tag
+/// must not be applied on top of that. `ignore_orientation` skips them.
+pub fn decode_handle(lib_heif: &LibHeif, handle: &ImageHandle, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+    let has_alpha = handle.has_alpha_channel();
+    let bits = handle.luma_bits_per_pixel();
+    let high_bit_depth = bits > 8;
+
+    let decoding_options = match options.ignore_orientation {
+        true => {
+            let mut decoding_options = DecodingOptions::new()
+                .ok_or_else(|| ConversionError::ImageError("Failed to allocate HEIF decoding options".to_string()))?;
+            decoding_options.set_ignore_transformations(true);
+            Some(decoding_options)
+        }
+        false => None,
+    };
+    let image = lib_heif
+        .decode(handle, ColorSpace::Rgb(chroma(high_bit_depth, has_alpha)), decoding_options)
+        .map_err(decode_error)?;
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| ConversionError::ImageError("HEIF decoder returned no pixel data".to_string()))?;
+
+    let channels = if has_alpha { 4 } else { 3 };
+    let (width, height) = (plane.width, plane.height);
+    let bytes_per_sample = if high_bit_depth { 2 } else { 1 };
+    let row_len = width as usize * channels * bytes_per_sample;
+
+    let rows = plane.data.chunks(plane.stride).take(height as usize).map(|row| &row[..row_len]);
+    let malformed = || ConversionError::ImageError("HEIF pixel data has an unexpected size".to_string());
+
+    if high_bit_depth {
+        // Samples are stored in the low `bits` of each u16; stretch them to the full range.
+        let max = ((1u32 << bits) - 1).max(1);
+        let samples: Vec<u16> = rows
+            .flat_map(|row| row.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])))
+            .map(|v| ((v as u32).min(max) * 65535 / max) as u16)
+            .collect();
+
+        if has_alpha {
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgba16).ok_or_else(malformed)
+        } else {
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgb16).ok_or_else(malformed)
+        }
+    } else {
+        let samples: Vec<u8> = rows.flatten().copied().collect();
+
+        if has_alpha {
+            ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgba8).ok_or_else(malformed)
+        } else {
+            ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgb8).ok_or_else(malformed)
+        }
+    }
+}
+
+/// Copies `img` into an interleaved RGB(A) libheif image ready for encoding.
+/// `bit_depth` above 8 produces little-endian samples scaled down from 16 bits.
+pub fn to_heif_image(img: &DynamicImage, bit_depth: u8) -> Result<Image, HeifError> {
+    let (width, height) = img.dimensions();
+    let has_alpha = img.color().has_alpha();
+    let high_bit_depth = bit_depth > 8;
+
+    let mut image = Image::new(width, height, ColorSpace::Rgb(chroma(high_bit_depth, has_alpha)))?;
+    image.create_plane(Channel::Interleaved, width, height, bit_depth)?;
+
+    let samples: Vec<u8> = match (high_bit_depth, has_alpha) {
+        (false, false) => img.to_rgb8().into_raw(),
+        (false, true) => img.to_rgba8().into_raw(),
+        (true, alpha) => {
+            let wide = if alpha { img.to_rgba16().into_raw() } else { img.to_rgb16().into_raw() };
+            let shift = 16 - bit_depth.min(16);
+            wide.into_iter().flat_map(|v| (v >> shift).to_le_bytes()).collect()
+        }
+    };
+
+    if let Some(plane) = image.planes_mut().interleaved {
+        let row_len = samples.len() / height.max(1) as usize;
+        for (dst, src) in plane.data.chunks_mut(plane.stride).zip(samples.chunks(row_len)) {
+            dst[..row_len].copy_from_slice(src);
+        }
+    }
+
+    Ok(image)
+}
+
+fn chroma(high_bit_depth: bool, has_alpha: bool) -> RgbChroma {
+    match (high_bit_depth, has_alpha) {
+        (false, false) => RgbChroma::Rgb,
+        (false, true) => RgbChroma::Rgba,
+        (true, false) => RgbChroma::HdrRgbLe,
+        (true, true) => RgbChroma::HdrRgbaLe,
+    }
+}
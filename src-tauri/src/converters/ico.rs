@@ -1,58 +1,197 @@
-use image::ImageReader;
+//! ICO container reading and writing, shared with CUR, which differs only in
+//! its resource type and the hotspot stored in each directory entry.
+
+use image::codecs::png::PngEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageEncoder, ImageFormat, ImageReader, RgbaImage};
+use std::io::Cursor;
 use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_ico(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ico" => {
-            let resized = img.resize_exact(256, 256, image::imageops::FilterType::Lanczos3);
-            let rgba_img = resized.to_rgba8();
-            rgba_img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions, IcoEntryFormat, IcoOptions};
+
+const INFO: FormatInfo = FormatInfo::image("ico", "ICO").options(&["ico"]).encodable().alpha();
+
+const DIR_HEADER_SIZE: usize = 6;
+const DIR_ENTRY_SIZE: usize = 16;
+const BITMAP_HEADER_SIZE: u32 = 40;
+const ICON_TYPE: u16 = 1;
+const CURSOR_TYPE: u16 = 2;
+
+pub struct IcoConverter;
+
+impl Converter for IcoConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, _options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        decode_largest(input_path)
+    }
+
+    fn decode_pages(&self, input_path: &Path, options: &ConversionOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+        if !options.ico.extract_all {
+            return Ok(vec![decode_largest(input_path)?]);
+        }
+        decode_entries(&std::fs::read(input_path)?)
+    }
+
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        write_icon(img, &options.ico, None, output_path)
+    }
+}
+
+/// The entry with the highest colour depth and size.
+pub fn decode_largest(input_path: &Path) -> Result<DynamicImage, ConversionError> {
+    let mut reader = ImageReader::open(input_path).map_err(|e| ConversionError::ImageError(e.to_string()))?;
+    reader.set_format(ImageFormat::Ico);
+    reader.decode().map_err(|e| ConversionError::ImageError(e.to_string()))
+}
+
+/// Decodes every entry in directory order. Each one is repackaged as a
+/// single-entry icon so the `image` decoder handles its PNG or BMP payload.
+pub fn decode_entries(data: &[u8]) -> Result<Vec<DynamicImage>, ConversionError> {
+    let malformed = || ConversionError::ImageError("Malformed ICO: directory is truncated".to_string());
+    let header = data.get(..DIR_HEADER_SIZE).ok_or_else(malformed)?;
+    let resource_type = u16::from_le_bytes([header[2], header[3]]);
+    let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+
+    (0..count)
+        .map(|index| {
+            let start = DIR_HEADER_SIZE + index * DIR_ENTRY_SIZE;
+            let entry = data.get(start..start + DIR_ENTRY_SIZE).ok_or_else(malformed)?;
+            let size = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as usize;
+            let offset = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as usize;
+            let payload = data.get(offset..offset + size).ok_or_else(malformed)?;
+
+            let mut single = Vec::with_capacity(DIR_HEADER_SIZE + DIR_ENTRY_SIZE + size);
+            single.extend_from_slice(&[0, 0]);
+            single.extend_from_slice(&ICON_TYPE.to_le_bytes());
+            single.extend_from_slice(&1u16.to_le_bytes());
+            single.extend_from_slice(&entry[..8]);
+            if resource_type == CURSOR_TYPE {
+                // The planes and bit count fields hold the hotspot in cursors.
+                single[DIR_HEADER_SIZE + 4..DIR_HEADER_SIZE + 8].fill(0);
+            }
+            single.extend_from_slice(&(size as u32).to_le_bytes());
+            single.extend_from_slice(&((DIR_HEADER_SIZE + DIR_ENTRY_SIZE) as u32).to_le_bytes());
+            single.extend_from_slice(payload);
+
+            image::load_from_memory_with_format(&single, ImageFormat::Ico)
+                .map_err(|e| ConversionError::ImageError(format!("Failed to decode icon entry {}: {}", index + 1, e)))
+        })
+        .collect()
+}
+
+/// Writes an icon, or a cursor when `hotspot` is given, with one entry per
+/// requested size. The hotspot is in source pixels and scaled per entry.
+pub fn write_icon(img: &DynamicImage, options: &IcoOptions, hotspot: Option<(u32, u32)>, output_path: &Path) -> Result<(), ConversionError> {
+    let mut sizes: Vec<u32> = options.sizes.clone();
+    sizes.sort_unstable();
+    sizes.dedup();
+    if sizes.is_empty() || sizes.iter().any(|&s| s == 0 || s > 256) {
+        return Err(ConversionError::ConversionFailed("Icon sizes must be between 1 and 256".to_string()));
+    }
+
+    let (source_width, source_height) = img.dimensions();
+    let mut entries = Vec::with_capacity(sizes.len());
+    for &size in &sizes {
+        let square = fit_square(img, size);
+        let png = match options.entry_format {
+            IcoEntryFormat::Png => true,
+            IcoEntryFormat::Bmp => false,
+            IcoEntryFormat::Auto => size == 256,
+        };
+        let payload = if png { encode_png(&square)? } else { encode_bitmap(&square) };
+
+        // Icons store colour planes and bit count here; cursors store the hotspot,
+        // scaled through the same fit as the pixels so it stays on the same feature.
+        let fields = match hotspot {
+            Some((x, y)) => {
+                let scale = size as f64 / source_width.max(source_height).max(1) as f64;
+                let pad_x = (size as f64 - source_width as f64 * scale) / 2.0;
+                let pad_y = (size as f64 - source_height as f64 * scale) / 2.0;
+                let map = |v: u32, pad: f64| ((v as f64 * scale + pad).round() as u32).min(size - 1) as u16;
+                (map(x, pad_x), map(y, pad_y))
+            }
+            None => (1, 32),
+        };
+        entries.push((size, fields, payload));
     }
 
+    let resource_type = if hotspot.is_some() { CURSOR_TYPE } else { ICON_TYPE };
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(&resource_type.to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+
+    let mut offset = DIR_HEADER_SIZE + entries.len() * DIR_ENTRY_SIZE;
+    for (size, (first, second), payload) in &entries {
+        // 256 is stored as 0.
+        let dimension = if *size == 256 { 0 } else { *size as u8 };
+        out.extend_from_slice(&[dimension, dimension, 0, 0]);
+        out.extend_from_slice(&first.to_le_bytes());
+        out.extend_from_slice(&second.to_le_bytes());
+        out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += payload.len();
+    }
+    for (_, _, payload) in &entries {
+        out.extend_from_slice(payload);
+    }
+
+    std::fs::write(output_path, out)?;
     Ok(())
 }
+
+/// Scales the image to fit a `size` square, centred on transparency.
+fn fit_square(img: &DynamicImage, size: u32) -> RgbaImage {
+    let resized = img.resize(size, size, FilterType::Lanczos3).to_rgba8();
+    let mut square = RgbaImage::new(size, size);
+    let x = (size - resized.width()) / 2;
+    let y = (size - resized.height()) / 2;
+    image::imageops::overlay(&mut square, &resized, x as i64, y as i64);
+    square
+}
+
+fn encode_png(img: &RgbaImage) -> Result<Vec<u8>, ConversionError> {
+    let mut data = Vec::new();
+    PngEncoder::new(Cursor::new(&mut data))
+        .write_image(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgba8)
+        .map_err(|e| ConversionError::ImageError(format!("Failed to encode icon entry: {}", e)))?;
+    Ok(data)
+}
+
+/// A 32-bit DIB: a header giving double height, bottom-up BGRA rows, then
+/// the 1-bit AND mask older readers use for transparency.
+fn encode_bitmap(img: &RgbaImage) -> Vec<u8> {
+    let (width, height) = img.dimensions();
+    let mask_stride = width.div_ceil(32) * 4;
+    let image_size = width * height * 4 + mask_stride * height;
+
+    let mut data = Vec::with_capacity((BITMAP_HEADER_SIZE + image_size) as usize);
+    data.extend_from_slice(&BITMAP_HEADER_SIZE.to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    data.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&32u16.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&image_size.to_le_bytes());
+    data.extend_from_slice(&[0; 16]);
+
+    for row in img.rows().rev() {
+        for pixel in row {
+            let [r, g, b, a] = pixel.0;
+            data.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+    for row in img.rows().rev() {
+        let mut mask = vec![0u8; mask_stride as usize];
+        for (x, pixel) in row.enumerate() {
+            if pixel.0[3] == 0 {
+                mask[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        data.extend_from_slice(&mask);
+    }
+    data
+}
@@ -1,54 +1,110 @@
-use image::ImageReader;
+use image::DynamicImage;
+use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
 use std::path::Path;
-use crate::models::ConversionError;
+use super::metadata::Metadata;
+use super::registry::{self, Converter, FormatInfo};
+use crate::models::{ChromaSubsampling, ConversionError, ConversionOptions};
 
-pub fn convert_jpg(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("jpg", "JPEG").aliases(&["jpeg", "jfif"]).options(&["jpeg"]).encodable();
+
+const APP1: u8 = 0xE1;
+const APP2: u8 = 0xE2;
+const APP13: u8 = 0xED;
+/// Segment length is a u16 that counts itself.
+const MAX_SEGMENT_PAYLOAD: usize = u16::MAX as usize - 2;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const IPTC_HEADER: &[u8] = b"Photoshop 3.0\0";
+
+pub struct JpegConverter;
+
+impl Converter for JpegConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        registry::read_image(input_path, options)
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ico" => {
-            img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    /// Greyscale sources stay single-channel; everything else is written as
+    /// 8-bit YCbCr with the requested chroma subsampling.
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let (width, height) = (img.width(), img.height());
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(ConversionError::ImageError(format!("JPEG cannot store a {}x{} image", width, height)));
+        }
+
+        let options = &options.jpeg;
+        let mut data = Vec::new();
+        let mut encoder = Encoder::new(&mut data, options.quality.clamp(1, 100));
+        encoder.set_sampling_factor(match options.chroma {
+            ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+            ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+        });
+        encoder.set_progressive(options.progressive);
+
+        let result = if img.color().has_color() {
+            encoder.encode(img.to_rgb8().as_raw(), width as u16, height as u16, ColorType::Rgb)
+        } else {
+            encoder.encode(img.to_luma8().as_raw(), width as u16, height as u16, ColorType::Luma)
+        };
+        result.map_err(|e| ConversionError::ImageError(format!("Failed to save JPEG: {}", e)))?;
+
+        std::fs::write(output_path, data)?;
+        Ok(())
     }
 
-    Ok(())
+    /// Inserts APP segments after the JFIF header: EXIF and XMP in APP1, the
+    /// ICC profile split across APP2 chunks, IPTC in APP13. EXIF and XMP
+    /// larger than one segment are dropped.
+    fn embed_metadata(&self, output_path: &Path, metadata: &Metadata) -> Result<(), ConversionError> {
+        let data = std::fs::read(output_path)?;
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return Err(ConversionError::ImageError("Written JPEG has no start-of-image marker".to_string()));
+        }
+        let insert_at = match data.get(2..4) {
+            Some([0xFF, 0xE0]) => 4 + u16::from_be_bytes([data[4], data[5]]) as usize,
+            _ => 2,
+        };
+
+        let mut segments = Vec::new();
+        if let Some(exif) = &metadata.exif {
+            push_segment(&mut segments, APP1, &[EXIF_HEADER, exif]);
+        }
+        if let Some(xmp) = &metadata.xmp {
+            push_segment(&mut segments, APP1, &[XMP_HEADER, xmp]);
+        }
+        if let Some(icc) = &metadata.icc {
+            let chunks: Vec<&[u8]> = icc.chunks(MAX_SEGMENT_PAYLOAD - ICC_HEADER.len() - 2).collect();
+            for (index, chunk) in chunks.iter().enumerate() {
+                push_segment(&mut segments, APP2, &[ICC_HEADER, &[index as u8 + 1, chunks.len() as u8], chunk]);
+            }
+        }
+        if let Some(iptc) = &metadata.iptc {
+            push_segment(&mut segments, APP13, &[IPTC_HEADER, iptc]);
+        }
+
+        let mut out = Vec::with_capacity(data.len() + segments.len());
+        out.extend_from_slice(&data[..insert_at]);
+        out.extend_from_slice(&segments);
+        out.extend_from_slice(&data[insert_at..]);
+        std::fs::write(output_path, out)?;
+        Ok(())
+    }
+}
+
+/// Appends a marker segment made of `parts`, skipping it if it won't fit.
+fn push_segment(out: &mut Vec<u8>, marker: u8, parts: &[&[u8]]) {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    if len > MAX_SEGMENT_PAYLOAD {
+        return;
+    }
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&((len + 2) as u16).to_be_bytes());
+    for part in parts {
+        out.extend_from_slice(part);
+    }
 }
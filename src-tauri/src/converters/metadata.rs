@@ -0,0 +1,357 @@
+//! Source metadata carried over to the output: EXIF, XMP, IPTC and the ICC
+//! profile, with the privacy filters applied before anything is written.
+
+use image::metadata::Orientation;
+use image::{ImageDecoder, ImageReader};
+use std::path::Path;
+use crate::models::ConversionOptions;
+
+const GPS_IFD_TAG: u16 = 0x8825;
+const EXIF_IFD_TAG: u16 = 0x8769;
+const INTEROP_IFD_TAG: u16 = 0xA005;
+/// ImageDescription, Make, Model, Software, DateTime, Artist and Copyright:
+/// the IFD0 tags TIFF output takes over from the source's EXIF.
+const DESCRIPTIVE_TAGS: &[u16] = &[270, 271, 272, 305, 306, 315, 33432];
+/// CameraSerialNumber (DNG), BodySerialNumber, LensSerialNumber and MakerNote.
+const SERIAL_TAGS: &[u16] = &[0xC62F, 0xA431, 0xA435, 0x927C];
+const XMP_SERIAL_PROPERTIES: &[&str] = &["aux:SerialNumber", "aux:LensSerialNumber", "exifEX:BodySerialNumber", "exifEX:LensSerialNumber"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// A TIFF-structured EXIF block, without the `Exif\0\0` prefix.
+    pub exif: Option<Vec<u8>>,
+    /// The XMP packet as UTF-8 XML.
+    pub xmp: Option<Vec<u8>>,
+    /// Photoshop image resources holding IPTC-IIM, as stored in JPEG APP13.
+    pub iptc: Option<Vec<u8>>,
+    pub icc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.xmp.is_none() && self.iptc.is_none() && self.icc.is_none()
+    }
+}
+
+/// Reads metadata through the `image` decoders. Files they can't open simply
+/// have none.
+pub fn read(input_path: &Path) -> Metadata {
+    let Ok(mut decoder) = ImageReader::open(input_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(image::ImageError::from)
+        .and_then(|reader| reader.into_decoder())
+    else {
+        return Metadata::default();
+    };
+
+    Metadata {
+        exif: decoder.exif_metadata().ok().flatten(),
+        xmp: decoder.xmp_metadata().ok().flatten(),
+        // PNG keeps IPTC as ImageMagick hex text, which no target here can store.
+        iptc: decoder.iptc_metadata().ok().flatten().filter(|iptc| iptc.starts_with(b"8BIM")),
+        icc: decoder.icc_profile().ok().flatten(),
+    }
+}
+
+/// Applies the removal options and, when the pixels were rotated upright on
+/// decode, resets the orientation so viewers don't rotate them again.
+pub fn prepare(mut metadata: Metadata, options: &ConversionOptions) -> Metadata {
+    let filters = &options.metadata;
+    let oriented = !options.ignore_orientation;
+
+    if let Some(exif) = metadata.exif.as_mut() {
+        if oriented {
+            let _ = Orientation::remove_from_exif_chunk(exif);
+        }
+        if filters.remove_gps {
+            remove_gps(exif);
+        }
+        if filters.remove_serial_numbers {
+            remove_serial_numbers(exif);
+        }
+    }
+
+    metadata.xmp = metadata.xmp.map(|xmp| {
+        let xmp = String::from_utf8_lossy(&xmp);
+        remove_xmp_properties(&xmp, |name| {
+            (oriented && name == "tiff:Orientation")
+                || (filters.remove_gps && name.starts_with("exif:GPS"))
+                || (filters.remove_serial_numbers && XMP_SERIAL_PROPERTIES.contains(&name))
+        })
+        .into_bytes()
+    });
+
+    metadata
+}
+
+/// Minimal in-place access to a TIFF-structured EXIF block.
+struct Exif<'a> {
+    data: &'a mut [u8],
+    big_endian: bool,
+}
+
+impl<'a> Exif<'a> {
+    fn new(data: &'a mut [u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            [b'I', b'I', 42, 0] => false,
+            [b'M', b'M', 0, 42] => true,
+            _ => return None,
+        };
+        Some(Exif { data, big_endian })
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?];
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn value_size(&self, entry: usize) -> Option<usize> {
+        match self.u16(entry + 2)? {
+            1 | 2 | 6 | 7 => Some(1),
+            3 | 8 => Some(2),
+            4 | 9 | 11 | 13 => Some(4),
+            5 | 10 | 12 => Some(8),
+            _ => None,
+        }
+    }
+
+    fn set_u16(&mut self, offset: usize, value: u16) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.data[offset..offset + 2].copy_from_slice(&bytes);
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32(4).map(|offset| offset as usize)
+    }
+
+    /// Offsets of each 12-byte entry in the directory at `ifd`.
+    fn entries(&self, ifd: usize) -> Option<Vec<usize>> {
+        let count = self.u16(ifd)? as usize;
+        let end = ifd + 2 + count * 12 + 4;
+        (end <= self.data.len()).then(|| (0..count).map(|i| ifd + 2 + i * 12).collect())
+    }
+
+    fn find(&self, ifd: usize, tag: u16) -> Option<usize> {
+        self.entries(ifd)?.into_iter().find(|&entry| self.u16(entry) == Some(tag))
+    }
+
+    /// The directory a pointer tag such as GPSInfo refers to.
+    fn sub_ifd(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let entry = self.find(ifd, tag)?;
+        self.u32(entry + 8).map(|offset| offset as usize)
+    }
+
+    /// Zeroes a value stored outside its entry, so removed data doesn't
+    /// linger in the block.
+    fn clear_value(&mut self, entry: usize) {
+        let (Some(size), Some(count), Some(offset)) = (self.value_size(entry), self.u32(entry + 4), self.u32(entry + 8)) else { return };
+        let len = size * count as usize;
+        if len > 4 {
+            let start = (offset as usize).min(self.data.len());
+            let end = start.saturating_add(len).min(self.data.len());
+            self.data[start..end].fill(0);
+        }
+    }
+
+    /// Drops an entry, moving later entries and the next-directory offset up.
+    fn remove(&mut self, ifd: usize, tag: u16) {
+        let Some(entry) = self.find(ifd, tag) else { return };
+        let Some(count) = self.u16(ifd) else { return };
+        self.clear_value(entry);
+
+        let end = ifd + 2 + count as usize * 12 + 4;
+        self.data.copy_within(entry + 12..end, entry);
+        self.data[end - 12..end].fill(0);
+        self.set_u16(ifd, count - 1);
+    }
+
+    /// Zeroes a whole directory and every value it points to.
+    fn clear_ifd(&mut self, ifd: usize) {
+        let Some(entries) = self.entries(ifd) else { return };
+        for &entry in &entries {
+            self.clear_value(entry);
+        }
+        self.data[ifd..ifd + 2 + entries.len() * 12].fill(0);
+    }
+}
+
+/// Moves EXIF into a TIFF file being written, `out`. The EXIF and GPS
+/// directories are appended to `out` in its byte order, with every value
+/// relocated. Returns the IFD0 entries to add: pointers to those directories
+/// and the [`DESCRIPTIVE_TAGS`] the source has.
+pub fn tiff_entries(exif: &[u8], out: &mut Vec<u8>, big_endian: bool) -> Vec<[u8; 12]> {
+    let mut block = exif.to_vec();
+    let Some(exif) = Exif::new(&mut block) else { return Vec::new() };
+    let Some(ifd0) = exif.first_ifd() else { return Vec::new() };
+    let Some(entries) = exif.entries(ifd0) else { return Vec::new() };
+
+    let mut writer = IfdWriter { exif: &exif, out, big_endian };
+    entries
+        .into_iter()
+        .filter_map(|entry| match exif.u16(entry)? {
+            EXIF_IFD_TAG => writer.pointer(entry, &[INTEROP_IFD_TAG]),
+            GPS_IFD_TAG => writer.pointer(entry, &[]),
+            tag if DESCRIPTIVE_TAGS.contains(&tag) => writer.entry(entry),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Copies directories out of an EXIF block into a TIFF file with its own
+/// byte order, where values stored outside their entries get new offsets.
+struct IfdWriter<'a, 'b> {
+    exif: &'a Exif<'a>,
+    out: &'b mut Vec<u8>,
+    big_endian: bool,
+}
+
+impl IfdWriter<'_, '_> {
+    fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+    }
+
+    fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+    }
+
+    /// Appends `value` at a word boundary and returns its offset.
+    fn append(&mut self, value: &[u8]) -> Option<u32> {
+        self.out.resize(self.out.len() + self.out.len() % 2, 0);
+        let offset = u32::try_from(self.out.len()).ok()?;
+        self.out.extend_from_slice(value);
+        Some(offset)
+    }
+
+    fn header(&self, tag: u16, kind: u16, count: u32) -> [u8; 12] {
+        let mut entry = [0u8; 12];
+        entry[..2].copy_from_slice(&self.u16_bytes(tag));
+        entry[2..4].copy_from_slice(&self.u16_bytes(kind));
+        entry[4..8].copy_from_slice(&self.u32_bytes(count));
+        entry
+    }
+
+    /// Copies one entry, storing its value inline or appended to `out`.
+    /// Entries of unknown types, or whose value lies outside the block, are dropped.
+    fn entry(&mut self, entry: usize) -> Option<[u8; 12]> {
+        let exif = self.exif;
+        let (tag, kind, count) = (exif.u16(entry)?, exif.u16(entry + 2)?, exif.u32(entry + 4)?);
+        let size = exif.value_size(entry)?;
+        let len = size.checked_mul(count as usize)?;
+        let start = if len <= 4 { entry + 8 } else { exif.u32(entry + 8)? as usize };
+        let mut value = exif.data.get(start..start.checked_add(len)?)?.to_vec();
+
+        if exif.big_endian != self.big_endian {
+            // Rationals are two 32-bit numbers; doubles swap as a whole.
+            let unit = if matches!(kind, 5 | 10) { 4 } else { size };
+            value.chunks_exact_mut(unit).for_each(|sample| sample.reverse());
+        }
+
+        let mut new_entry = self.header(tag, kind, count);
+        if len <= 4 {
+            new_entry[8..8 + len].copy_from_slice(&value);
+        } else {
+            let offset = self.append(&value)?;
+            new_entry[8..].copy_from_slice(&self.u32_bytes(offset));
+        }
+        Some(new_entry)
+    }
+
+    /// Copies the directory a pointer entry refers to, following `pointers`
+    /// inside it, and returns the entry pointing at the copy.
+    fn pointer(&mut self, entry: usize, pointers: &[u16]) -> Option<[u8; 12]> {
+        let exif = self.exif;
+        let ifd = exif.u32(entry + 8)? as usize;
+        let mut entries: Vec<[u8; 12]> = exif
+            .entries(ifd)?
+            .into_iter()
+            .filter_map(|entry| match exif.u16(entry)? {
+                tag if pointers.contains(&tag) => self.pointer(entry, &[]),
+                _ => self.entry(entry),
+            })
+            .collect();
+        let big_endian = self.big_endian;
+        entries.sort_by_key(|entry| if big_endian { u16::from_be_bytes([entry[0], entry[1]]) } else { u16::from_le_bytes([entry[0], entry[1]]) });
+
+        let mut directory = self.u16_bytes(entries.len() as u16).to_vec();
+        directory.extend(entries.iter().flatten());
+        directory.extend_from_slice(&[0; 4]);
+        let offset = self.append(&directory)?;
+
+        let mut new_entry = self.header(exif.u16(entry)?, 4, 1);
+        new_entry[8..].copy_from_slice(&self.u32_bytes(offset));
+        Some(new_entry)
+    }
+}
+
+fn remove_gps(exif: &mut [u8]) {
+    let Some(mut exif) = Exif::new(exif) else { return };
+    let Some(ifd0) = exif.first_ifd() else { return };
+    if let Some(gps) = exif.sub_ifd(ifd0, GPS_IFD_TAG) {
+        exif.clear_ifd(gps);
+    }
+    exif.remove(ifd0, GPS_IFD_TAG);
+}
+
+fn remove_serial_numbers(exif: &mut [u8]) {
+    let Some(mut exif) = Exif::new(exif) else { return };
+    let Some(ifd0) = exif.first_ifd() else { return };
+    let exif_ifd = exif.sub_ifd(ifd0, EXIF_IFD_TAG);
+    for ifd in std::iter::once(ifd0).chain(exif_ifd) {
+        for &tag in SERIAL_TAGS {
+            exif.remove(ifd, tag);
+        }
+    }
+}
+
+/// Removes XMP properties whose qualified name matches, whether written as
+/// attributes (`exif:GPSLatitude="..."`) or elements (`<exif:GPSLatitude>`).
+fn remove_xmp_properties(xmp: &str, remove: impl Fn(&str) -> bool) -> String {
+    let bytes = xmp.as_bytes();
+    let is_name_byte = |b: &u8| b.is_ascii_alphanumeric() || matches!(b, b':' | b'_' | b'-' | b'.');
+
+    let mut out = String::with_capacity(xmp.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c == b'<' || c.is_ascii_whitespace() {
+            let name_start = i + 1;
+            let name_end = name_start + bytes[name_start..].iter().take_while(|b| is_name_byte(b)).count();
+            let name = &xmp[name_start..name_end];
+            if !name.is_empty() && remove(name) {
+                let end = if c == b'<' { element_end(xmp, name_end, name) } else { attribute_end(xmp, name_end) };
+                if let Some(end) = end {
+                    out.push_str(&xmp[copied..i]);
+                    copied = end;
+                    i = end;
+                    continue;
+                }
+            }
+        }
+        i += 1;
+    }
+    out.push_str(&xmp[copied..]);
+    out
+}
+
+fn element_end(xmp: &str, from: usize, name: &str) -> Option<usize> {
+    let open_end = from + xmp[from..].find('>')?;
+    if xmp[..open_end].ends_with('/') {
+        return Some(open_end + 1);
+    }
+    let close = format!("</{}>", name);
+    xmp[open_end..].find(&close).map(|at| open_end + at + close.len())
+}
+
+fn attribute_end(xmp: &str, from: usize) -> Option<usize> {
+    let rest = xmp[from..].trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|q| *q == '"' || *q == '\'')?;
+    let value_start = xmp.len() - rest.len() + 1;
+    xmp[value_start..].find(quote).map(|at| value_start + at + 1)
+}
@@ -1,6 +1,7 @@
+pub mod registry;
+pub mod sniff;
 pub mod png;
 pub mod jpg;
-pub mod jpeg;
 pub mod webp;
 pub mod bmp;
 pub mod gif;
@@ -8,6 +9,7 @@ pub mod tiff;
 pub mod ico;
 pub mod avif;
 pub mod heic;
+pub mod heif;
 pub mod ppm;
 pub mod tga;
 pub mod dds;
@@ -20,4 +22,13 @@ pub mod psd;
 pub mod fits;
 pub mod dcm;
 pub mod pcx;
-pub mod video;pub mod utils;
\ No newline at end of file
+pub mod animation;
+pub mod color;
+pub mod depth;
+pub mod metadata;
+pub mod quantize;
+pub mod tonemap;
+pub mod transform;
+pub mod transparency;
+pub mod video;
+pub mod utils;
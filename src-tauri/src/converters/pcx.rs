@@ -1,50 +1,262 @@
-use image::ImageReader;
+use color_quant::NeuQuant;
+use image::{DynamicImage, Rgb, RgbImage, RgbaImage};
+use std::collections::HashMap;
 use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_pcx(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
-    }
-
-    Ok(())
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions, PcxColors};
+
+const INFO: FormatInfo = FormatInfo::image("pcx", "PCX").options(&["pcx"]).encodable();
+
+const HEADER_SIZE: usize = 128;
+const MANUFACTURER: u8 = 0x0A;
+/// Marker byte preceding the 256-colour palette at the end of the file.
+const PALETTE_MARKER: u8 = 0x0C;
+const PALETTE_SIZE: usize = 768;
+/// Bytes with both top bits set are run counts.
+const RUN_FLAG: u8 = 0xC0;
+const MAX_RUN: usize = 0x3F;
+
+/// Default palette for version 3 files, which carry none.
+const EGA_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0x00, 0x00, 0xAA], [0x00, 0xAA, 0x00], [0x00, 0xAA, 0xAA],
+    [0xAA, 0x00, 0x00], [0xAA, 0x00, 0xAA], [0xAA, 0x55, 0x00], [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55], [0x55, 0x55, 0xFF], [0x55, 0xFF, 0x55], [0x55, 0xFF, 0xFF],
+    [0xFF, 0x55, 0x55], [0xFF, 0x55, 0xFF], [0xFF, 0xFF, 0x55], [0xFF, 0xFF, 0xFF],
+];
+
+pub struct PcxConverter;
+
+impl Converter for PcxConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, _options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let data = std::fs::read(input_path)?;
+        decode(&data)
+    }
+
+    /// Writes version 5 RLE data, either as 8-bit indices with a 256-entry
+    /// palette or as three 8-bit colour planes. PCX has no alpha channel.
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let rgb = img.to_rgb8();
+        let (width, height) = rgb.dimensions();
+        if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(ConversionError::ImageError(format!("PCX cannot store a {}x{} image", width, height)));
+        }
+
+        let indexed = match options.pcx.colors {
+            PcxColors::Indexed => Some(exact_palette(&rgb).unwrap_or_else(|| quantize(&rgb))),
+            PcxColors::Auto => exact_palette(&rgb),
+            PcxColors::Rgb => None,
+        };
+
+        // Scanlines are padded to an even number of bytes per plane.
+        let bytes_per_line = (width as usize).div_ceil(2) * 2;
+        let planes = if indexed.is_some() { 1 } else { 3 };
+
+        let mut out = header(width as u16, height as u16, planes, bytes_per_line as u16);
+        let mut line = vec![0u8; bytes_per_line];
+        for y in 0..height {
+            match &indexed {
+                Some((indices, _)) => {
+                    let row = &indices[(y * width) as usize..((y + 1) * width) as usize];
+                    line[..row.len()].copy_from_slice(row);
+                    encode_rle(&line, &mut out);
+                }
+                None => {
+                    for channel in 0..3 {
+                        for x in 0..width {
+                            line[x as usize] = rgb.get_pixel(x, y)[channel];
+                        }
+                        encode_rle(&line, &mut out);
+                    }
+                }
+            }
+        }
+
+        if let Some((_, palette)) = &indexed {
+            out.push(PALETTE_MARKER);
+            out.extend(palette.iter().flatten());
+            out.resize(out.len() + PALETTE_SIZE - palette.len() * 3, 0);
+        }
+
+        std::fs::write(output_path, out)?;
+        Ok(())
+    }
+}
+
+fn malformed(what: &str) -> ConversionError {
+    ConversionError::ImageError(format!("Malformed PCX: {}", what))
+}
+
+fn decode(data: &[u8]) -> Result<DynamicImage, ConversionError> {
+    if data.len() < HEADER_SIZE || data[0] != MANUFACTURER {
+        return Err(malformed("missing header"));
+    }
+
+    let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
+    let version = data[1];
+    let encoded = data[2] == 1;
+    let bits = data[3] as usize;
+    let (x_min, y_min, x_max, y_max) = (word(4), word(6), word(8), word(10));
+    let planes = data[65] as usize;
+    let bytes_per_line = word(66);
+
+    if x_max < x_min || y_max < y_min {
+        return Err(malformed("invalid image bounds"));
+    }
+    let (width, height) = (x_max - x_min + 1, y_max - y_min + 1);
+    let line_len = planes * bytes_per_line;
+    if line_len == 0 || bytes_per_line * 8 < width * bits {
+        return Err(malformed("invalid scanline length"));
+    }
+
+    let pixels = &data[HEADER_SIZE..];
+    let scanlines = if encoded { decode_rle(pixels, line_len * height) } else { pixels.get(..line_len * height).map(<[u8]>::to_vec) }
+        .ok_or_else(|| malformed("image data is truncated"))?;
+    let line = |y: usize, plane: usize| &scanlines[y * line_len + plane * bytes_per_line..][..bytes_per_line];
+
+    let header_palette = || -> Vec<[u8; 3]> {
+        if version == 3 {
+            EGA_PALETTE.to_vec()
+        } else {
+            data[16..64].chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect()
+        }
+    };
+
+    let (width, height) = (width as u32, height as u32);
+    let image = match (bits, planes) {
+        (8, 1) => {
+            // The 256-colour palette trails the image data; without one the indices are grey levels.
+            let palette: Vec<[u8; 3]> = match data.len().checked_sub(PALETTE_SIZE + 1) {
+                Some(start) if start >= HEADER_SIZE && data[start] == PALETTE_MARKER => {
+                    data[start + 1..].chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect()
+                }
+                _ => (0..=255).map(|v| [v, v, v]).collect(),
+            };
+            RgbImage::from_fn(width, height, |x, y| Rgb(palette[line(y as usize, 0)[x as usize] as usize]))
+                .into()
+        }
+        (8, 3) => RgbImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x as usize, y as usize);
+            Rgb([line(y, 0)[x], line(y, 1)[x], line(y, 2)[x]])
+        })
+        .into(),
+        (8, 4) => RgbaImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x as usize, y as usize);
+            image::Rgba([line(y, 0)[x], line(y, 1)[x], line(y, 2)[x], line(y, 3)[x]])
+        })
+        .into(),
+        (1, 1) => RgbImage::from_fn(width, height, |x, y| {
+            let bit = packed_index(line(y as usize, 0), x as usize, 1);
+            if bit == 0 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) }
+        })
+        .into(),
+        // EGA/VGA 16-colour: one bit of the index per plane.
+        (1, 2..=4) => {
+            let palette = header_palette();
+            RgbImage::from_fn(width, height, |x, y| {
+                let index = (0..planes).fold(0, |index, plane| index | (packed_index(line(y as usize, plane), x as usize, 1) << plane));
+                Rgb(palette[index])
+            })
+            .into()
+        }
+        (2 | 4, 1) => {
+            let palette = header_palette();
+            RgbImage::from_fn(width, height, |x, y| Rgb(palette[packed_index(line(y as usize, 0), x as usize, bits)]))
+                .into()
+        }
+        _ => return Err(malformed(&format!("unsupported layout: {} bits per pixel in {} planes", bits, planes))),
+    };
+
+    Ok(image)
+}
+
+/// Reads the `bits`-wide index of pixel `x` from a packed, MSB-first scanline.
+fn packed_index(line: &[u8], x: usize, bits: usize) -> usize {
+    let bit = x * bits;
+    let shift = 8 - bits - bit % 8;
+    ((line[bit / 8] >> shift) & ((1 << bits) - 1) as u8) as usize
+}
+
+/// Expands RLE data until `len` bytes are produced. Runs may cross scanlines.
+fn decode_rle(data: &[u8], len: usize) -> Option<Vec<u8>> {
+    // `len` comes from the header; a run byte expands to at most 63 bytes.
+    let mut out = Vec::with_capacity(len.min(data.len().saturating_mul(63)));
+    let mut bytes = data.iter();
+    while out.len() < len {
+        let byte = *bytes.next()?;
+        if byte & RUN_FLAG == RUN_FLAG {
+            let value = *bytes.next()?;
+            out.extend(std::iter::repeat_n(value, (byte & !RUN_FLAG) as usize));
+        } else {
+            out.push(byte);
+        }
+    }
+    out.truncate(len);
+    Some(out)
+}
+
+/// Run-length encodes one plane of a scanline. Literal bytes that look like
+/// run counts are written as runs of one.
+fn encode_rle(line: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < line.len() {
+        let value = line[i];
+        let run = line[i..].iter().take(MAX_RUN).take_while(|&&b| b == value).count();
+        if run > 1 || value & RUN_FLAG == RUN_FLAG {
+            out.push(RUN_FLAG | run as u8);
+        }
+        out.push(value);
+        i += run;
+    }
+}
+
+fn header(width: u16, height: u16, planes: u8, bytes_per_line: u16) -> Vec<u8> {
+    let mut header = vec![0u8; HEADER_SIZE];
+    header[0] = MANUFACTURER;
+    header[1] = 5;
+    header[2] = 1;
+    header[3] = 8;
+    header[8..10].copy_from_slice(&(width - 1).to_le_bytes());
+    header[10..12].copy_from_slice(&(height - 1).to_le_bytes());
+    header[12..14].copy_from_slice(&72u16.to_le_bytes());
+    header[14..16].copy_from_slice(&72u16.to_le_bytes());
+    header[65] = planes;
+    header[66..68].copy_from_slice(&bytes_per_line.to_le_bytes());
+    // Palette info: 1 = colour.
+    header[68] = 1;
+    header
+}
+
+/// Indices into a palette of the image's own colours, if it has at most 256.
+fn exact_palette(rgb: &RgbImage) -> Option<(Vec<u8>, Vec<[u8; 3]>)> {
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut lookup: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(rgb.len() / 3);
+
+    for pixel in rgb.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(&index) => index,
+            None if palette.len() < 256 => {
+                let index = palette.len() as u8;
+                palette.push(pixel.0);
+                lookup.insert(pixel.0, index);
+                index
+            }
+            None => return None,
+        };
+        indices.push(index);
+    }
+    Some((indices, palette))
+}
+
+/// Reduces the image to 256 colours with NeuQuant.
+fn quantize(rgb: &RgbImage) -> (Vec<u8>, Vec<[u8; 3]>) {
+    let rgba = DynamicImage::ImageRgb8(rgb.clone()).to_rgba8();
+    let quant = NeuQuant::new(10, 256, rgba.as_raw());
+    let indices = rgba.pixels().map(|p| quant.index_of(&p.0) as u8).collect();
+    let palette = quant.color_map_rgb().chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+    (indices, palette)
 }
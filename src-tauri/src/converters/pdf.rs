@@ -1,50 +1,85 @@
-use image::ImageReader;
+use hayro::hayro_interpret::InterpreterSettings;
+use hayro::hayro_syntax::Pdf;
+use hayro::vello_cpu::color::AlphaColor;
+use hayro::{PixmapSettings, RenderCache, RenderSettings};
+use image::{DynamicImage, RgbaImage};
 use std::path::Path;
-use crate::models::ConversionError;
+use std::sync::Arc;
+use super::registry::{Converter, FormatInfo};
+use super::utils;
+use crate::models::{ConversionError, ConversionOptions, PdfOptions};
 
-pub fn convert_pdf(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("pdf", "PDF").options(&["pdf"]);
+
+pub struct PdfConverter;
+
+impl Converter for PdfConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        self.decode_pages(input_path, options)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ConversionError::ImageError("PDF has no pages to render".to_string()))
+    }
+
+    fn decode_pages(&self, input_path: &Path, options: &ConversionOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+        let data = std::fs::read(input_path)?;
+        render_pages(data, &options.pdf)
     }
+}
+
+pub fn render_pages(data: Vec<u8>, options: &PdfOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+    let pdf = Pdf::new(Arc::new(data))
+        .map_err(|e| ConversionError::ImageError(format!("Failed to open PDF: {:?}", e)))?;
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    let pages = pdf.pages();
+    let selected = match &options.pages {
+        Some(spec) => utils::parse_index_ranges(spec, pages.len())?,
+        None => (0..pages.len()).collect(),
+    };
+    if selected.is_empty() {
+        return Err(ConversionError::ImageError("PDF has no pages to render".to_string()));
     }
 
-    Ok(())
+    // PDF user space is 72 units per inch.
+    let scale = if options.dpi > 0.0 { options.dpi / 72.0 } else { 1.0 };
+    let [r, g, b, a] = options.background.0;
+    let pixmap_settings = PixmapSettings {
+        x_scale: scale,
+        y_scale: scale,
+        bg_color: AlphaColor::from_rgba8(r, g, b, a),
+    };
+
+    let cache = RenderCache::new();
+    let interpreter_settings = InterpreterSettings::default();
+    let render_settings = RenderSettings::default();
+
+    selected
+        .into_iter()
+        .map(|index| {
+            let pixmap = hayro::render(&pages[index], &cache, &interpreter_settings, &render_settings, &pixmap_settings);
+            let (width, height) = (pixmap.width() as u32, pixmap.height() as u32);
+
+            // Pixmaps hold premultiplied alpha; image expects straight alpha.
+            let pixels = pixmap
+                .data()
+                .iter()
+                .flat_map(|p| match p.a {
+                    0 => [0, 0, 0, 0],
+                    255 => [p.r, p.g, p.b, 255],
+                    a => {
+                        let unpremultiply = |c: u8| ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8;
+                        [unpremultiply(p.r), unpremultiply(p.g), unpremultiply(p.b), a]
+                    }
+                })
+                .collect();
+
+            RgbaImage::from_raw(width, height, pixels)
+                .map(DynamicImage::ImageRgba8)
+                .ok_or_else(|| ConversionError::ImageError(format!("Failed to build image for PDF page {}", index + 1)))
+        })
+        .collect()
 }
@@ -1,54 +1,118 @@
-use image::ImageReader;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, ImageEncoder};
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::Path;
-use crate::models::ConversionError;
+use super::metadata::Metadata;
+use super::registry::{self, Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions, PngCompression, PngFilter};
 
-pub fn convert_png(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("png", "PNG").options(&["png"]).encodable().alpha().bit_depth(16);
+
+/// Signature plus the 13-byte IHDR chunk with its length, type and CRC.
+const IHDR_END: usize = 8 + 12 + 13;
+
+pub struct PngConverter;
+
+impl Converter for PngConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        registry::read_image(input_path, options)
+    }
+
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        // PNG has no float samples; everything else is written as decoded.
+        let img = match img {
+            DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb16(img.to_rgb16()),
+            DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgba16(img.to_rgba16()),
+            _ => img.clone(),
+        };
+
+        let file = BufWriter::new(File::create(output_path)?);
+        encoder(file, options)
+            .write_image(img.as_bytes(), img.width(), img.height(), img.color().into())
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save PNG: {}", e)))
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ico" => {
-            img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    /// Inserts `iCCP`, `eXIf` and an XMP `iTXt` chunk after `IHDR`. PNG has no
+    /// standard place for IPTC, so it is left out.
+    fn embed_metadata(&self, output_path: &Path, metadata: &Metadata) -> Result<(), ConversionError> {
+        let data = std::fs::read(output_path)?;
+        if data.len() < IHDR_END || &data[12..16] != b"IHDR" {
+            return Err(ConversionError::ImageError("Written PNG does not start with IHDR".to_string()));
+        }
+
+        let mut chunks = Vec::new();
+        if let Some(icc) = &metadata.icc {
+            let mut body = b"ICC Profile\0\0".to_vec();
+            let mut zlib = ZlibEncoder::new(&mut body, Compression::default());
+            zlib.write_all(icc)?;
+            zlib.finish()?;
+            push_chunk(&mut chunks, b"iCCP", &body);
+        }
+        if let Some(exif) = &metadata.exif {
+            push_chunk(&mut chunks, b"eXIf", exif);
+        }
+        if let Some(xmp) = &metadata.xmp {
+            // Keyword, then uncompressed with empty language and translated keyword.
+            let mut body = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+            body.extend_from_slice(xmp);
+            push_chunk(&mut chunks, b"iTXt", &body);
+        }
+
+        let mut out = Vec::with_capacity(data.len() + chunks.len());
+        out.extend_from_slice(&data[..IHDR_END]);
+        out.extend_from_slice(&chunks);
+        out.extend_from_slice(&data[IHDR_END..]);
+        std::fs::write(output_path, out)?;
+        Ok(())
+    }
+}
+
+/// A PNG encoder with the requested compression level and filter.
+pub fn encoder<W: Write>(writer: W, options: &ConversionOptions) -> PngEncoder<W> {
+    let compression = match options.png.compression {
+        PngCompression::Fast => CompressionType::Fast,
+        PngCompression::Default => CompressionType::Default,
+        PngCompression::Best => CompressionType::Best,
+        PngCompression::None => CompressionType::Level(0),
+    };
+    let filter = match options.png.filter {
+        PngFilter::Adaptive => FilterType::Adaptive,
+        PngFilter::None => FilterType::NoFilter,
+        PngFilter::Sub => FilterType::Sub,
+        PngFilter::Up => FilterType::Up,
+        PngFilter::Avg => FilterType::Avg,
+        PngFilter::Paeth => FilterType::Paeth,
+    };
+    PngEncoder::new_with_quality(writer, compression, filter)
+}
+
+/// The type and body of every chunk after the signature, stopping at the
+/// first truncated one.
+pub fn read_chunks(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+    while let Some(header) = data.get(offset..offset + 8) {
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let Some(body) = data.get(offset + 8..offset + 8 + len) else { break };
+        chunks.push(([header[4], header[5], header[6], header[7]], body));
+        offset += 12 + len;
     }
+    chunks
+}
 
-    Ok(())
+pub fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(body);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
 }
@@ -1,50 +1,684 @@
-use image::ImageReader;
-use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_psd(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
-    }
-
-    Ok(())
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, ImageBuffer, Luma, Rgba, Rgb};
+use serde::Serialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions};
+
+const INFO: FormatInfo = FormatInfo::image("psd", "Photoshop").aliases(&["psb"]).options(&["psd"]);
+
+pub struct PsdConverter;
+
+impl Converter for PsdConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, _options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let data = std::fs::read(input_path)?;
+        let document = Document::parse(&data)?;
+        document.composite()
+    }
+
+    fn export_extras(&self, input_path: &Path, output_path: &Path, options: &ConversionOptions) -> Result<Vec<PathBuf>, ConversionError> {
+        if !options.psd.export_layers {
+            return Ok(Vec::new());
+        }
+
+        let data = std::fs::read(input_path)?;
+        let document = Document::parse(&data)?;
+        export_layers(&document, output_path)
+    }
+}
+
+/// Position and blending details written alongside exported layer PNGs.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LayerManifestEntry {
+    name: String,
+    file: String,
+    left: i32,
+    top: i32,
+    width: u32,
+    height: u32,
+    opacity: u8,
+    blend_mode: String,
+}
+
+fn export_layers(document: &Document, output_path: &Path) -> Result<Vec<PathBuf>, ConversionError> {
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let dir = output_path.parent().unwrap_or(Path::new("."));
+
+    let mut written = Vec::new();
+    let mut manifest = Vec::new();
+
+    for (index, layer) in document.layers.iter().enumerate() {
+        if !layer.visible || layer.is_group_marker || layer.width() == 0 || layer.height() == 0 {
+            continue;
+        }
+
+        let img = document.layer_image(layer)?;
+        let file_name = format!("{}_layer_{:03}_{}.png", stem, index + 1, sanitize_file_name(&layer.name));
+        let path = dir.join(&file_name);
+        img.save_with_format(&path, image::ImageFormat::Png)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save layer '{}': {}", layer.name, e)))?;
+
+        manifest.push(LayerManifestEntry {
+            name: layer.name.clone(),
+            file: file_name,
+            left: layer.left,
+            top: layer.top,
+            width: layer.width(),
+            height: layer.height(),
+            opacity: layer.opacity,
+            blend_mode: layer.blend_mode.clone(),
+        });
+        written.push(path);
+    }
+
+    let manifest_path = dir.join(format!("{}_layers.json", stem));
+    let json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| ConversionError::ConversionFailed(format!("Failed to write layer manifest: {}", e)))?;
+    std::fs::write(&manifest_path, json)?;
+    written.push(manifest_path);
+
+    Ok(written)
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if cleaned.is_empty() { "layer".to_string() } else { cleaned }
+}
+
+fn malformed(what: &str) -> ConversionError {
+    ConversionError::ImageError(format!("Malformed PSD: {}", what))
+}
+
+/// Big-endian cursor over the file bytes.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ConversionError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| malformed("unexpected end of file"))?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), ConversionError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    /// The offset `len` bytes past the current position.
+    fn end_of(&self, len: usize) -> Result<usize, ConversionError> {
+        self.pos.checked_add(len).ok_or_else(|| malformed("section too large"))
+    }
+
+    fn u8(&mut self) -> Result<u8, ConversionError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ConversionError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, ConversionError> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, ConversionError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, ConversionError> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn u64(&mut self) -> Result<u64, ConversionError> {
+        let b = self.bytes(8)?;
+        Ok(u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    /// Section lengths are 32-bit in PSD and 64-bit in PSB.
+    fn length(&mut self, large: bool) -> Result<usize, ConversionError> {
+        let len = if large { self.u64()? } else { self.u32()? as u64 };
+        usize::try_from(len).map_err(|_| malformed("section too large"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorMode {
+    Grayscale,
+    Indexed,
+    Rgb,
+    Cmyk,
+    Duotone,
+}
+
+struct LayerChannel {
+    id: i16,
+    /// Compression marker plus pixel data, exactly as stored.
+    data_range: std::ops::Range<usize>,
+}
+
+struct Layer {
+    name: String,
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+    opacity: u8,
+    blend_mode: String,
+    visible: bool,
+    is_group_marker: bool,
+    channels: Vec<LayerChannel>,
+}
+
+impl Layer {
+    fn width(&self) -> u32 {
+        (self.right - self.left).max(0) as u32
+    }
+
+    fn height(&self) -> u32 {
+        (self.bottom - self.top).max(0) as u32
+    }
+}
+
+struct Document<'a> {
+    data: &'a [u8],
+    large: bool,
+    channels: usize,
+    width: u32,
+    height: u32,
+    depth: u16,
+    color_mode: ColorMode,
+    palette: &'a [u8],
+    merged_alpha: bool,
+    layers: Vec<Layer>,
+    image_data_offset: usize,
+}
+
+impl<'a> Document<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, ConversionError> {
+        let mut r = Reader::new(data);
+
+        if r.bytes(4)? != b"8BPS" {
+            return Err(malformed("missing 8BPS signature"));
+        }
+        let large = match r.u16()? {
+            1 => false,
+            2 => true,
+            v => return Err(malformed(&format!("unknown version {}", v))),
+        };
+        r.skip(6)?;
+        let channels = r.u16()? as usize;
+        let height = r.u32()?;
+        let width = r.u32()?;
+        let depth = r.u16()?;
+        let color_mode = match r.u16()? {
+            1 => ColorMode::Grayscale,
+            2 => ColorMode::Indexed,
+            3 => ColorMode::Rgb,
+            4 => ColorMode::Cmyk,
+            8 => ColorMode::Duotone,
+            mode => return Err(ConversionError::UnsupportedFormat(format!("PSD colour mode {}", mode))),
+        };
+        if !matches!(depth, 8 | 16 | 32) {
+            return Err(ConversionError::UnsupportedFormat(format!("PSD bit depth {}", depth)));
+        }
+
+        let color_data_len = r.u32()? as usize;
+        let palette = r.bytes(color_data_len)?;
+
+        let resources_len = r.u32()? as usize;
+        r.skip(resources_len)?;
+
+        let layer_section_len = r.length(large)?;
+        let layer_section_end = r.end_of(layer_section_len)?;
+        let (layers, merged_alpha) = if layer_section_len > 0 {
+            parse_layer_section(&mut r, large, layer_section_end)?
+        } else {
+            (Vec::new(), false)
+        };
+
+        Ok(Document {
+            data,
+            large,
+            channels,
+            width,
+            height,
+            depth,
+            color_mode,
+            palette,
+            merged_alpha,
+            layers,
+            image_data_offset: layer_section_end,
+        })
+    }
+
+    /// The flattened image stored at the end of the file.
+    fn composite(&self) -> Result<DynamicImage, ConversionError> {
+        let mut r = Reader::new(self.data);
+        r.pos = self.image_data_offset;
+
+        let compression = r.u16()?;
+        let plane_count = self.channels;
+        let color_planes = match self.color_mode {
+            ColorMode::Rgb => 3,
+            ColorMode::Cmyk => 4,
+            _ => 1,
+        };
+        if plane_count < color_planes {
+            return Err(malformed("too few channels for colour mode"));
+        }
+        let planes = read_planes(&mut r, compression, plane_count, self.width, self.height, self.depth, self.large)?;
+        let alpha = if self.merged_alpha { planes.get(color_planes) } else { None };
+
+        self.assemble(&planes[..color_planes], alpha.map(|a| a.as_slice()), self.width, self.height)
+    }
+
+    fn layer_image(&self, layer: &Layer) -> Result<DynamicImage, ConversionError> {
+        let (width, height) = (layer.width(), layer.height());
+        let color_planes = match self.color_mode {
+            ColorMode::Rgb => 3,
+            ColorMode::Cmyk => 4,
+            _ => 1,
+        };
+
+        let mut planes: Vec<Option<Vec<u16>>> = vec![None; color_planes];
+        let mut alpha = None;
+
+        for channel in &layer.channels {
+            let target = match channel.id {
+                -1 => &mut alpha,
+                id if id >= 0 && (id as usize) < color_planes => &mut planes[id as usize],
+                // Masks use their own rectangle and are not needed for the export.
+                _ => continue,
+            };
+
+            // Every channel starts with its own 2-byte compression field.
+            if channel.data_range.len() < 2 {
+                return Err(malformed("layer channel too short"));
+            }
+            let mut r = Reader::new(self.data);
+            r.pos = channel.data_range.start;
+            let compression = r.u16()?;
+            *target = match compression {
+                2 | 3 => {
+                    let len = channel.data_range.end.checked_sub(r.pos).ok_or_else(|| malformed("layer channel too short"))?;
+                    let compressed = r.bytes(len)?;
+                    Some(inflate_plane(compressed, compression == 3, width, height, self.depth)?)
+                }
+                _ => read_planes(&mut r, compression, 1, width, height, self.depth, self.large)?.pop(),
+            };
+        }
+
+        let filled = (width as usize) * (height as usize);
+        let full = if self.depth == 8 { 255 } else { 65535 };
+        let planes: Vec<Vec<u16>> = planes
+            .into_iter()
+            .map(|p| p.unwrap_or_else(|| vec![0; filled]))
+            .collect();
+        let alpha = alpha.unwrap_or_else(|| vec![full; filled]);
+
+        self.assemble(&planes, Some(&alpha), width, height)
+    }
+
+    /// Builds an image from planar channel data. Samples are widened to u16 while
+    /// reading; 8-bit documents keep values in 0..=255.
+    fn assemble(&self, planes: &[Vec<u16>], alpha: Option<&[u16]>, width: u32, height: u32) -> Result<DynamicImage, ConversionError> {
+        let pixels = (width as usize) * (height as usize);
+        let max = if self.depth == 8 { 255u32 } else { 65535u32 };
+        let sample = |plane: &[u16], i: usize| plane.get(i).copied().unwrap_or(0) as u32;
+
+        let rgb_at = |i: usize| -> [u32; 3] {
+            match self.color_mode {
+                ColorMode::Rgb => [sample(&planes[0], i), sample(&planes[1], i), sample(&planes[2], i)],
+                ColorMode::Cmyk => {
+                    // Photoshop stores CMYK inverted: max means no ink.
+                    let k = sample(&planes[3], i);
+                    [0, 1, 2].map(|c| sample(&planes[c], i) * k / max)
+                }
+                ColorMode::Indexed => {
+                    let index = sample(&planes[0], i) as usize;
+                    [0, 1, 2].map(|c| self.palette.get(c * 256 + index).copied().unwrap_or(0) as u32)
+                }
+                ColorMode::Grayscale | ColorMode::Duotone => {
+                    let v = sample(&planes[0], i);
+                    [v, v, v]
+                }
+            }
+        };
+
+        let gray = matches!(self.color_mode, ColorMode::Grayscale | ColorMode::Duotone);
+        let wide = self.depth > 8 && self.color_mode != ColorMode::Indexed;
+
+        let img = match (gray, alpha, wide) {
+            (true, None, false) => {
+                let data = (0..pixels).map(|i| sample(&planes[0], i) as u8).collect();
+                DynamicImage::ImageLuma8(ImageBuffer::<Luma<u8>, _>::from_raw(width, height, data).ok_or_else(|| malformed("pixel data"))?)
+            }
+            (true, None, true) => {
+                let data = (0..pixels).map(|i| sample(&planes[0], i) as u16).collect();
+                DynamicImage::ImageLuma16(ImageBuffer::<Luma<u16>, _>::from_raw(width, height, data).ok_or_else(|| malformed("pixel data"))?)
+            }
+            (_, None, false) => {
+                let data = (0..pixels).flat_map(|i| rgb_at(i).map(|v| v as u8)).collect();
+                DynamicImage::ImageRgb8(ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, data).ok_or_else(|| malformed("pixel data"))?)
+            }
+            (_, None, true) => {
+                let data = (0..pixels).flat_map(|i| rgb_at(i).map(|v| v as u16)).collect();
+                DynamicImage::ImageRgb16(ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, data).ok_or_else(|| malformed("pixel data"))?)
+            }
+            (_, Some(alpha), false) => {
+                let data = (0..pixels)
+                    .flat_map(|i| {
+                        let [r, g, b] = rgb_at(i);
+                        [r as u8, g as u8, b as u8, sample(alpha, i) as u8]
+                    })
+                    .collect();
+                DynamicImage::ImageRgba8(ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data).ok_or_else(|| malformed("pixel data"))?)
+            }
+            (_, Some(alpha), true) => {
+                let data = (0..pixels)
+                    .flat_map(|i| {
+                        let [r, g, b] = rgb_at(i);
+                        [r as u16, g as u16, b as u16, sample(alpha, i) as u16]
+                    })
+                    .collect();
+                DynamicImage::ImageRgba16(ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, data).ok_or_else(|| malformed("pixel data"))?)
+            }
+        };
+
+        Ok(img)
+    }
+}
+
+/// Reads the layer and mask section. 8-bit documents keep their layers in
+/// the layer info; 16- and 32-bit ones leave that empty and store them in an
+/// `Lr16` or `Lr32` block among the global additional layer info.
+fn parse_layer_section(r: &mut Reader, large: bool, section_end: usize) -> Result<(Vec<Layer>, bool), ConversionError> {
+    let info_len = r.length(large)?;
+    let info_end = r.end_of(info_len)?;
+    if info_len > 0 {
+        let layers = parse_layers(r, large, info_end)?;
+        if !layers.0.is_empty() {
+            return Ok(layers);
+        }
+    }
+    r.pos = info_end;
+
+    let mask_len = r.u32()? as usize;
+    r.skip(mask_len)?;
+
+    while section_end.saturating_sub(r.pos) >= 12 {
+        let signature = r.bytes(4)?;
+        if signature != b"8BIM" && signature != b"8B64" {
+            break;
+        }
+        let key = r.bytes(4)?;
+        let len = if large && is_long_block(key) { r.length(true)? } else { r.u32()? as usize };
+        if key == b"Lr16" || key == b"Lr32" {
+            let block_end = r.end_of(len)?;
+            return parse_layers(r, large, block_end);
+        }
+        // Global blocks are padded to a multiple of 4 bytes.
+        r.pos = r.end_of(len.checked_next_multiple_of(4).ok_or_else(|| malformed("section too large"))?)?;
+    }
+    Ok((Vec::new(), false))
+}
+
+/// Reads layer records and their channel data, which end at `info_end`.
+fn parse_layers(r: &mut Reader, large: bool, info_end: usize) -> Result<(Vec<Layer>, bool), ConversionError> {
+    // A negative count means the first alpha channel of the composite holds its transparency.
+    let raw_count = r.i16()?;
+    let merged_alpha = raw_count < 0;
+    let count = raw_count.unsigned_abs() as usize;
+
+    let mut layers = Vec::with_capacity(count);
+    let mut channel_lengths = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let top = r.i32()?;
+        let left = r.i32()?;
+        let bottom = r.i32()?;
+        let right = r.i32()?;
+
+        let channel_count = r.u16()? as usize;
+        let mut channels = Vec::with_capacity(channel_count);
+        for _ in 0..channel_count {
+            let id = r.i16()?;
+            let len = r.length(large)?;
+            channels.push((id, len));
+        }
+
+        if r.bytes(4)? != b"8BIM" {
+            return Err(malformed("bad blend mode signature"));
+        }
+        let blend_mode = String::from_utf8_lossy(r.bytes(4)?).trim().to_string();
+        let opacity = r.u8()?;
+        let _clipping = r.u8()?;
+        let flags = r.u8()?;
+        let _filler = r.u8()?;
+
+        let extra_len = r.u32()? as usize;
+        let extra_end = r.end_of(extra_len)?;
+
+        let mask_len = r.u32()? as usize;
+        r.skip(mask_len)?;
+        let ranges_len = r.u32()? as usize;
+        r.skip(ranges_len)?;
+
+        // Pascal string padded so the length byte plus text is a multiple of 4.
+        let name_len = r.u8()? as usize;
+        let mut name = String::from_utf8_lossy(r.bytes(name_len)?).to_string();
+        r.skip((4 - (name_len + 1) % 4) % 4)?;
+
+        let mut is_group_marker = false;
+        while r.pos + 12 <= extra_end {
+            let signature = r.bytes(4)?;
+            if signature != b"8BIM" && signature != b"8B64" {
+                break;
+            }
+            let key = r.bytes(4)?;
+            let len = if large && is_long_block(key) { r.length(true)? } else { r.u32()? as usize };
+            let block = r.bytes(len)?;
+
+            match key {
+                b"luni" if block.len() >= 4 => {
+                    let chars = u32::from_be_bytes([block[0], block[1], block[2], block[3]]) as usize;
+                    let units: Vec<u16> = block[4..]
+                        .chunks_exact(2)
+                        .take(chars)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect();
+                    let unicode = String::from_utf16_lossy(&units).trim_end_matches('\0').to_string();
+                    if !unicode.is_empty() {
+                        name = unicode;
+                    }
+                }
+                b"lsct" | b"lsdk" if block.len() >= 4 => {
+                    let kind = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+                    is_group_marker = kind != 0;
+                }
+                _ => {}
+            }
+        }
+        r.pos = extra_end;
+
+        channel_lengths.push(channels);
+        layers.push(Layer {
+            name,
+            top,
+            left,
+            bottom,
+            right,
+            opacity,
+            blend_mode,
+            visible: flags & 0x02 == 0,
+            is_group_marker,
+            channels: Vec::new(),
+        });
+    }
+
+    // Channel image data follows all layer records, in the same order.
+    for (layer, channels) in layers.iter_mut().zip(channel_lengths) {
+        for (id, len) in channels {
+            let start = r.pos;
+            r.skip(len)?;
+            layer.channels.push(LayerChannel { id, data_range: start..start + len });
+        }
+    }
+
+    r.pos = info_end;
+    Ok((layers, merged_alpha))
+}
+
+/// Additional layer info blocks whose length field widens to 64 bits in PSB files.
+fn is_long_block(key: &[u8]) -> bool {
+    matches!(
+        key,
+        b"LMsk" | b"Lr16" | b"Lr32" | b"Layr" | b"Mt16" | b"Mt32" | b"Mtrn" | b"Alph" | b"FMsk" | b"lnk2" | b"FEid" | b"FXid" | b"PxSD"
+    )
+}
+
+/// Reads `count` planes of `width * height` samples. For RLE data all row byte
+/// counts come first, followed by the PackBits rows of every plane.
+fn read_planes(r: &mut Reader, compression: u16, count: usize, width: u32, height: u32, depth: u16, large: bool) -> Result<Vec<Vec<u16>>, ConversionError> {
+    let (row_bytes, plane_bytes) = plane_size(width, height, depth)?;
+    let height = height as usize;
+    let rows = count.checked_mul(height).ok_or_else(|| malformed("image too large"))?;
+
+    let mut planes = Vec::with_capacity(count);
+    match compression {
+        0 => {
+            for _ in 0..count {
+                let raw = r.bytes(plane_bytes)?;
+                planes.push(widen_samples(raw, depth));
+            }
+        }
+        1 => {
+            let mut row_lengths = Vec::with_capacity(rows.min(r.data.len()));
+            for _ in 0..rows {
+                row_lengths.push(if large { r.u32()? as usize } else { r.u16()? as usize });
+            }
+
+            let mut lengths = row_lengths.into_iter();
+            for _ in 0..count {
+                // PackBits expands at most 64 times, so don't trust the header alone.
+                let mut raw = Vec::with_capacity(plane_bytes.min(r.data.len().saturating_mul(64)));
+                for _ in 0..height {
+                    let len = lengths.next().unwrap_or(0);
+                    unpack_bits(r.bytes(len)?, row_bytes, &mut raw);
+                }
+                planes.push(widen_samples(&raw, depth));
+            }
+        }
+        other => {
+            return Err(ConversionError::UnsupportedFormat(format!("PSD compression {}", other)));
+        }
+    }
+
+    Ok(planes)
+}
+
+/// Bytes in one row and in one plane of samples.
+fn plane_size(width: u32, height: u32, depth: u16) -> Result<(usize, usize), ConversionError> {
+    let row_bytes = (width as usize).checked_mul((depth / 8) as usize);
+    row_bytes
+        .and_then(|row| Some((row, row.checked_mul(height as usize)?)))
+        .ok_or_else(|| malformed("image too large"))
+}
+
+/// Decompresses a ZIP-compressed layer channel (compression 2, or 3 with
+/// prediction). Predicted rows store each sample as the difference from the
+/// previous one; 32-bit rows also store the bytes of all samples grouped by
+/// significance, most significant first.
+fn inflate_plane(compressed: &[u8], predicted: bool, width: u32, height: u32, depth: u16) -> Result<Vec<u16>, ConversionError> {
+    let (row_bytes, plane_bytes) = plane_size(width, height, depth)?;
+    let mut raw = Vec::with_capacity(plane_bytes.min(compressed.len().saturating_mul(64)));
+    ZlibDecoder::new(compressed)
+        .take(plane_bytes as u64)
+        .read_to_end(&mut raw)
+        .map_err(|e| malformed(&format!("ZIP channel data: {}", e)))?;
+    raw.resize(plane_bytes, 0);
+
+    if predicted && row_bytes > 0 {
+        let width = width as usize;
+        for row in raw.chunks_exact_mut(row_bytes) {
+            match depth {
+                16 => {
+                    let mut previous = 0u16;
+                    for sample in row.chunks_exact_mut(2) {
+                        previous = previous.wrapping_add(u16::from_be_bytes([sample[0], sample[1]]));
+                        sample.copy_from_slice(&previous.to_be_bytes());
+                    }
+                }
+                _ => {
+                    for i in 1..row.len() {
+                        row[i] = row[i].wrapping_add(row[i - 1]);
+                    }
+                }
+            }
+            if depth == 32 {
+                let grouped = row.to_vec();
+                for (i, sample) in row.chunks_exact_mut(4).enumerate() {
+                    for (b, byte) in sample.iter_mut().enumerate() {
+                        *byte = grouped[b * width + i];
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(widen_samples(&raw, depth))
+}
+
+fn unpack_bits(mut input: &[u8], row_bytes: usize, out: &mut Vec<u8>) {
+    let target = out.len() + row_bytes;
+    while out.len() < target && !input.is_empty() {
+        let header = input[0] as i8;
+        input = &input[1..];
+        match header {
+            -128 => {}
+            n if n >= 0 => {
+                let len = (n as usize + 1).min(input.len());
+                out.extend_from_slice(&input[..len]);
+                input = &input[len..];
+            }
+            n => {
+                if let Some(&value) = input.first() {
+                    out.extend(std::iter::repeat_n(value, (1 - n as isize) as usize));
+                    input = &input[1..];
+                }
+            }
+        }
+    }
+    out.resize(target, 0);
+}
+
+/// Converts big-endian samples to u16. 32-bit float samples are mapped to 16-bit.
+fn widen_samples(raw: &[u8], depth: u16) -> Vec<u16> {
+    match depth {
+        8 => raw.iter().map(|&v| v as u16).collect(),
+        16 => raw.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect(),
+        _ => raw
+            .chunks_exact(4)
+            .map(|c| (f32::from_be_bytes([c[0], c[1], c[2], c[3]]).clamp(0.0, 1.0) * 65535.0).round() as u16)
+            .collect(),
+    }
 }
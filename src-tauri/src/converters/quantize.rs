@@ -0,0 +1,115 @@
+//! Palette reduction for indexed output: a palette fitted with NeuQuant (or
+//! the exact colours when few enough), and dithering onto it.
+
+use color_quant::NeuQuant;
+use image::RgbaImage;
+use std::collections::{HashMap, HashSet};
+use super::depth::BAYER;
+use crate::models::PaletteDither;
+
+/// NeuQuant sampling factor: 1 examines every pixel, 30 is fastest.
+const SAMPLE_FACTOR: i32 = 10;
+/// Pixels fed to NeuQuant at most when building one palette from many frames.
+const MAX_SAMPLES: usize = 1 << 20;
+
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    quant: Option<NeuQuant>,
+}
+
+impl Palette {
+    /// Fits at most `size` colours to the pixels of `images` with alpha of at
+    /// least `alpha_threshold`. Images with no more distinct colours than that
+    /// keep them exactly.
+    pub fn build(images: &[&RgbaImage], size: usize, alpha_threshold: u8) -> Palette {
+        let size = size.clamp(1, 256);
+        let opaque = || images.iter().flat_map(|img| img.pixels()).filter(|p| p[3] >= alpha_threshold);
+
+        let mut colors: Vec<[u8; 3]> = Vec::new();
+        let mut seen = HashSet::new();
+        for pixel in opaque() {
+            let rgb = [pixel[0], pixel[1], pixel[2]];
+            if seen.insert(rgb) {
+                if colors.len() == size {
+                    colors.clear();
+                    break;
+                }
+                colors.push(rgb);
+            }
+        }
+        if !colors.is_empty() {
+            return Palette { colors, quant: None };
+        }
+
+        let step = opaque().count().div_ceil(MAX_SAMPLES).max(1);
+        let samples: Vec<u8> = opaque().step_by(step).flat_map(|p| [p[0], p[1], p[2], 255]).collect();
+        if samples.is_empty() {
+            return Palette { colors: vec![[0, 0, 0]], quant: None };
+        }
+        let quant = NeuQuant::new(SAMPLE_FACTOR, size, &samples);
+        let colors = quant.color_map_rgb().chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+        Palette { colors, quant: Some(quant) }
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// The entry closest to `rgb`.
+    fn nearest(&self, rgb: [u8; 3]) -> u8 {
+        if let Some(quant) = &self.quant {
+            return quant.index_of(&[rgb[0], rgb[1], rgb[2], 255]) as u8;
+        }
+        let distance = |c: &[u8; 3]| (0..3).map(|i| (c[i] as i32 - rgb[i] as i32).pow(2)).sum::<i32>();
+        (0..self.colors.len()).min_by_key(|&i| distance(&self.colors[i])).unwrap_or(0) as u8
+    }
+}
+
+/// Maps every pixel to a palette index, dithering as requested. Pixels with
+/// alpha below `alpha_threshold` get `transparent` when it is given.
+pub fn index(img: &RgbaImage, palette: &Palette, transparent: Option<u8>, alpha_threshold: u8, dither: PaletteDither) -> Vec<u8> {
+    let width = img.width() as usize;
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut nearest = |rgb: [u8; 3]| *cache.entry(rgb).or_insert_with(|| palette.nearest(rgb));
+
+    // Ordered dithering spreads values by about one palette step per channel.
+    let spread = 255.0 / (palette.colors.len() as f32).cbrt();
+    // Floyd–Steinberg error for the current and next row, one pixel of padding each side.
+    let mut errors = [vec![[0f32; 3]; width + 2], vec![[0f32; 3]; width + 2]];
+
+    let mut indices = Vec::with_capacity(img.len() / 4);
+    for (y, row) in img.rows().enumerate() {
+        for (x, pixel) in row.enumerate() {
+            if let Some(transparent) = transparent.filter(|_| pixel[3] < alpha_threshold) {
+                indices.push(transparent);
+                continue;
+            }
+
+            let wanted: [f32; 3] = match dither {
+                PaletteDither::None => [0, 1, 2].map(|c| pixel[c] as f32),
+                PaletteDither::FloydSteinberg => [0, 1, 2].map(|c| pixel[c] as f32 + errors[0][x + 1][c]),
+                PaletteDither::Ordered => {
+                    let offset = ((BAYER[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5) * spread;
+                    [0, 1, 2].map(|c| pixel[c] as f32 + offset)
+                }
+            }
+            .map(|v| v.clamp(0.0, 255.0));
+            let index = nearest(wanted.map(|v| v.round() as u8));
+            indices.push(index);
+
+            if dither == PaletteDither::FloydSteinberg {
+                let chosen = palette.colors[index as usize];
+                for c in 0..3 {
+                    let error = wanted[c] - chosen[c] as f32;
+                    errors[0][x + 2][c] += error * 7.0 / 16.0;
+                    errors[1][x][c] += error * 3.0 / 16.0;
+                    errors[1][x + 1][c] += error * 5.0 / 16.0;
+                    errors[1][x + 2][c] += error / 16.0;
+                }
+            }
+        }
+        errors.swap(0, 1);
+        errors[1].fill([0.0; 3]);
+    }
+    indices
+}
@@ -0,0 +1,548 @@
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, ImageResult};
+use std::io::{BufRead, Seek};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use super::animation::{self, Animation};
+use super::metadata::{self, Metadata};
+use super::{color, depth, tonemap, transform, transparency, utils};
+use super::video::progress::Progress;
+use crate::models::{ConversionError, ConversionOptions, MetadataMode, MultiPageMode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatKind {
+    Image,
+    Video,
+}
+
+/// Static description of a format: its canonical id, the extensions it is
+/// also known by, and what the app can do with it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub kind: FormatKind,
+    pub can_decode: bool,
+    pub can_encode: bool,
+    /// Stores floating-point samples, so HDR sources are written without tone mapping.
+    pub hdr: bool,
+    /// Most bits per sample the format can store; deeper sources are reduced to fit.
+    pub bit_depth: u8,
+    /// Can store an alpha channel; other targets get transparent images flattened.
+    pub alpha: bool,
+    /// Holds a sequence of frames that can also be written out as video.
+    pub sequence: bool,
+    /// Can store timed frames, so animated sources keep every frame.
+    pub animated: bool,
+    /// Option groups this format reads from the conversion request.
+    pub options: &'static [&'static str],
+}
+
+impl FormatInfo {
+    pub const fn image(id: &'static str, name: &'static str) -> Self {
+        FormatInfo {
+            id,
+            name,
+            aliases: &[],
+            kind: FormatKind::Image,
+            can_decode: true,
+            can_encode: false,
+            hdr: false,
+            bit_depth: 8,
+            alpha: false,
+            sequence: false,
+            animated: false,
+            options: &[],
+        }
+    }
+
+    pub const fn video(id: &'static str, name: &'static str) -> Self {
+        FormatInfo {
+            kind: FormatKind::Video,
+            can_encode: true,
+            ..FormatInfo::image(id, name)
+        }
+    }
+
+    pub const fn aliases(mut self, aliases: &'static [&'static str]) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    pub const fn options(mut self, options: &'static [&'static str]) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub const fn encodable(mut self) -> Self {
+        self.can_encode = true;
+        self
+    }
+
+    pub const fn hdr(mut self) -> Self {
+        self.hdr = true;
+        self.bit_depth = depth::FLOAT_BITS;
+        self
+    }
+
+    pub const fn bit_depth(mut self, bits: u8) -> Self {
+        self.bit_depth = bits;
+        self
+    }
+
+    pub const fn alpha(mut self) -> Self {
+        self.alpha = true;
+        self
+    }
+
+    pub const fn sequence(mut self) -> Self {
+        self.sequence = true;
+        self
+    }
+
+    pub const fn animated(mut self) -> Self {
+        self.animated = true;
+        self
+    }
+
+    pub fn matches(&self, format: &str) -> bool {
+        let format = format.to_lowercase();
+        self.id == format || self.aliases.contains(&format.as_str())
+    }
+
+    /// The canonical id followed by every alias.
+    pub fn extensions(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.id).chain(self.aliases.iter().copied())
+    }
+}
+
+pub trait Converter: Send + Sync {
+    fn info(&self) -> &FormatInfo;
+
+    fn decode(&self, _input_path: &Path, _options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        Err(ConversionError::UnsupportedFormat(self.info().id.to_string()))
+    }
+
+    /// Decodes every page or frame selected by `options`. Single-image formats
+    /// return just their one image.
+    fn decode_pages(&self, input_path: &Path, options: &ConversionOptions) -> Result<Vec<DynamicImage>, ConversionError> {
+        Ok(vec![self.decode(input_path, options)?])
+    }
+
+    /// Writes additional files derived from the source next to `output_path`
+    /// (e.g. individual layers), returning their paths.
+    fn export_extras(&self, _input_path: &Path, _output_path: &Path, _options: &ConversionOptions) -> Result<Vec<PathBuf>, ConversionError> {
+        Ok(Vec::new())
+    }
+
+    fn encode(&self, _img: &DynamicImage, _output_path: &Path, _options: &ConversionOptions) -> Result<(), ConversionError> {
+        Err(ConversionError::UnsupportedFormat(self.info().id.to_string()))
+    }
+
+    /// Every frame of an animated source with its timing. Stills, and formats
+    /// that can't animate, return `None`.
+    fn decode_animation(&self, _input_path: &Path, _options: &ConversionOptions) -> Result<Option<Animation>, ConversionError> {
+        Ok(None)
+    }
+
+    /// Writes every frame of `animation`; only called for `animated` formats.
+    fn encode_animation(&self, _animation: &Animation, _output_path: &Path, _options: &ConversionOptions) -> Result<(), ConversionError> {
+        Err(ConversionError::UnsupportedFormat(self.info().id.to_string()))
+    }
+
+    /// EXIF, XMP, IPTC and ICC data stored with the source image.
+    fn metadata(&self, input_path: &Path) -> Metadata {
+        metadata::read(input_path)
+    }
+
+    /// Adds metadata to a file this converter has just written. Formats that
+    /// can't hold it leave the file as it is.
+    fn embed_metadata(&self, _output_path: &Path, _metadata: &Metadata) -> Result<(), ConversionError> {
+        Ok(())
+    }
+
+    /// Bits per sample written with these options. Pages deeper than this
+    /// are reduced before `encode`, and float pages are tone-mapped unless it
+    /// is 32.
+    fn output_bit_depth(&self, _options: &ConversionOptions) -> u8 {
+        self.info().bit_depth
+    }
+
+    /// Playback rate recorded in (or requested for) a frame sequence, used
+    /// when writing it as video.
+    fn frame_rate(&self, _input_path: &Path, _options: &ConversionOptions) -> Option<f32> {
+        None
+    }
+}
+
+/// Converter for formats handled entirely by the `image` crate.
+pub struct ImageCrateConverter {
+    info: FormatInfo,
+    format: Option<ImageFormat>,
+}
+
+impl ImageCrateConverter {
+    pub const fn new(info: FormatInfo, format: ImageFormat) -> Self {
+        ImageCrateConverter {
+            info: info.encodable(),
+            format: Some(format),
+        }
+    }
+
+    pub const fn decode_only(info: FormatInfo) -> Self {
+        ImageCrateConverter { info, format: None }
+    }
+}
+
+impl Converter for ImageCrateConverter {
+    fn info(&self) -> &FormatInfo {
+        &self.info
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        read_image(input_path, options)
+    }
+
+    fn encode(&self, img: &DynamicImage, output_path: &Path, _options: &ConversionOptions) -> Result<(), ConversionError> {
+        let format = self.format
+            .ok_or_else(|| ConversionError::UnsupportedFormat(self.info.id.to_string()))?;
+
+        img.save_with_format(output_path, format)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save {}: {}", self.info.name, e)))
+    }
+}
+
+/// Decodes any format the `image` crate recognises from the file contents,
+/// rotating it upright unless `options.ignore_orientation` is set.
+pub fn read_image(input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+    let reader = ImageReader::open(input_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| ConversionError::ImageError(format!("Failed to read image: {}", e)))?;
+    decode_oriented(reader, options)
+        .map_err(|e| ConversionError::ImageError(format!("Failed to decode image: {}", e)))
+}
+
+/// Decodes and applies the EXIF orientation the decoder reports (JPEG, TIFF,
+/// WebP and PNG carry one). Files without the tag decode unchanged.
+pub fn decode_oriented<R: BufRead + Seek>(reader: ImageReader<R>, options: &ConversionOptions) -> ImageResult<DynamicImage> {
+    let mut decoder = reader.into_decoder()?;
+    let orientation = if options.ignore_orientation { Orientation::NoTransforms } else { decoder.orientation()? };
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+pub struct Registry {
+    converters: Vec<Box<dyn Converter>>,
+}
+
+impl Registry {
+    fn new() -> Self {
+        let mut registry = Registry { converters: Vec::new() };
+
+        registry.register(super::png::PngConverter);
+        registry.register(super::jpg::JpegConverter);
+        registry.register(super::webp::WebpConverter);
+        registry.register(super::bmp::converter());
+        registry.register(super::gif::GifConverter);
+        registry.register(super::tiff::TiffConverter);
+        registry.register(super::ico::IcoConverter);
+        registry.register(super::ppm::converter());
+        registry.register(super::avif::AvifConverter);
+        registry.register(super::heic::HeicConverter);
+        registry.register(super::tga::TgaConverter);
+        registry.register(super::dds::DdsConverter);
+        registry.register(super::apng::ApngConverter);
+        registry.register(super::cur::CurConverter);
+        registry.register(super::exr::ExrConverter);
+        registry.register(super::svg::SvgConverter);
+        registry.register(super::pdf::PdfConverter);
+        registry.register(super::psd::PsdConverter);
+        registry.register(super::fits::FitsConverter);
+        registry.register(super::dcm::DicomConverter);
+        registry.register(super::pcx::PcxConverter);
+
+        for converter in super::video::formats::converters() {
+            registry.register(converter);
+        }
+
+        registry
+    }
+
+    pub fn register(&mut self, converter: impl Converter + 'static) {
+        self.converters.push(Box::new(converter));
+    }
+
+    pub fn find(&self, format: &str) -> Option<&dyn Converter> {
+        self.converters
+            .iter()
+            .find(|c| c.info().matches(format))
+            .map(|c| c.as_ref())
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &FormatInfo> {
+        self.converters.iter().map(|c| c.info())
+    }
+
+    /// The id of every format of the given kind that can be written. Aliases
+    /// name the same container, so they aren't offered as separate targets.
+    pub fn output_extensions(&self, kind: FormatKind) -> Vec<String> {
+        self.formats()
+            .filter(|info| info.kind == kind && info.can_encode)
+            .map(|info| info.id.to_string())
+            .collect()
+    }
+
+    /// Every extension (ids and aliases) of the given kind that can be read.
+    pub fn input_extensions(&self, kind: FormatKind) -> Vec<String> {
+        self.formats()
+            .filter(|info| info.kind == kind && info.can_decode)
+            .flat_map(|info| info.extensions())
+            .map(|ext| ext.to_string())
+            .collect()
+    }
+}
+
+/// Frame rate for sequences that don't record one.
+const DEFAULT_FRAME_RATE: f32 = 10.0;
+
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::new)
+}
+
+/// The outcome of a successful conversion.
+#[derive(Debug, Clone, Default)]
+pub struct Converted {
+    /// Every file written; multi-page sources may produce several.
+    pub files: Vec<PathBuf>,
+    /// Things the user should know about the output, such as precision lost
+    /// to a shallower target.
+    pub notes: Vec<String>,
+}
+
+/// Converts `input_path` to `output_path`, dispatching on the registered formats.
+/// Jobs run through FFmpeg report their progress to `on_progress`.
+pub fn convert(
+    input_path: &str,
+    output_path: &str,
+    from_format: &str,
+    to_format: &str,
+    options: &ConversionOptions,
+    on_progress: &dyn Fn(Progress),
+) -> Result<Converted, ConversionError> {
+    let registry = registry();
+
+    let source = registry
+        .find(from_format)
+        .filter(|c| c.info().can_decode)
+        .ok_or_else(|| ConversionError::UnsupportedFormat(from_format.to_string()))?;
+
+    if source.info().kind == FormatKind::Video {
+        let target = registry.find(to_format).filter(|c| c.info().kind == FormatKind::Image && c.info().can_encode);
+        match target {
+            Some(target) if target.info().animated => {
+                super::video::transcoder::convert_to_animation(input_path, output_path, to_format, options, on_progress)?;
+            }
+            Some(target) => return export_video_frames(input_path, target, Path::new(output_path), options, on_progress),
+            None => super::video::transcoder::convert_video(input_path, output_path, to_format, on_progress)?,
+        }
+        return Ok(Converted { files: vec![PathBuf::from(output_path)], ..Converted::default() });
+    }
+
+    if Path::new(input_path).is_dir() && utils::is_video_format(to_format) {
+        return convert_folder_to_video(source, Path::new(input_path), Path::new(output_path), to_format, options, on_progress);
+    }
+
+    if source.info().sequence && utils::is_video_format(to_format) {
+        return convert_sequence_to_video(source, Path::new(input_path), Path::new(output_path), to_format, options, on_progress);
+    }
+
+    let target = registry
+        .find(to_format)
+        .filter(|c| c.info().kind == FormatKind::Image && c.info().can_encode)
+        .ok_or_else(|| ConversionError::UnsupportedFormat(to_format.to_string()))?;
+
+    let input_path = Path::new(input_path);
+    if !input_path.exists() {
+        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+    }
+
+    if target.info().animated {
+        if let Some(animation) = source.decode_animation(input_path, options)?.filter(|a| a.frames.len() > 1) {
+            let animation = animation::prepare(animation, target.info().alpha, options)?;
+            target.encode_animation(&animation, Path::new(output_path), options)?;
+            return Ok(Converted { files: vec![PathBuf::from(output_path)], ..Converted::default() });
+        }
+    }
+
+    let bit_depth = target.output_bit_depth(options);
+    let mut pages = source.decode_pages(input_path, options)?;
+    let source_bits = pages.iter().map(depth::sample_bits).max().unwrap_or(8);
+    if bit_depth < depth::FLOAT_BITS {
+        pages = pages.into_iter().map(|page| tonemap::to_display(page, &options.tone_map)).collect();
+    }
+
+    let reads_metadata = options.metadata.mode == MetadataMode::Copy || options.color.convert || options.color.embed_profile;
+    let mut metadata = if reads_metadata { source.metadata(input_path) } else { Metadata::default() };
+    let (mut pages, output_icc) = color::apply(pages, metadata.icc.take(), &options.color)?;
+    pages = pages.into_iter().map(|page| transform::apply(page, &options.transform)).collect::<Result<_, _>>()?;
+    pages = pages.into_iter().map(|page| transparency::apply(page, target.info().alpha, &options.transparency)).collect();
+    pages = pages.into_iter().map(|page| depth::reduce(page, bit_depth, &options.bit_depth)).collect();
+    let output_path = Path::new(output_path);
+
+    let mut written = write_pages(&pages, target, output_path, options)?;
+    let mut metadata = match options.metadata.mode {
+        MetadataMode::Copy => metadata::prepare(metadata, options),
+        MetadataMode::Strip => Metadata::default(),
+    };
+    metadata.icc = output_icc
+        .filter(|_| options.color.embed_profile)
+        .filter(|icc| pages.iter().all(|page| color::describes(icc, page)));
+    if !metadata.is_empty() {
+        for path in &written {
+            target.embed_metadata(path, &metadata)?;
+        }
+    }
+    written.extend(source.export_extras(input_path, output_path, options)?);
+    let notes = depth::loss_note(source_bits, bit_depth, &options.bit_depth).into_iter().collect();
+    Ok(Converted { files: written, notes })
+}
+
+/// Decodes every frame of an image sequence and hands them to FFmpeg.
+fn convert_sequence_to_video(
+    source: &dyn Converter,
+    input_path: &Path,
+    output_path: &Path,
+    to_format: &str,
+    options: &ConversionOptions,
+    on_progress: &dyn Fn(Progress),
+) -> Result<Converted, ConversionError> {
+    if !input_path.exists() {
+        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+    }
+
+    let frames = source
+        .decode_pages(input_path, options)?
+        .into_iter()
+        .map(|frame| video_frame(frame, options));
+    let frame_rate = source.frame_rate(input_path, options).unwrap_or(DEFAULT_FRAME_RATE);
+    super::video::transcoder::encode_frames(frames, frame_rate, &output_path.to_string_lossy(), to_format, on_progress)?;
+
+    let mut written = vec![output_path.to_path_buf()];
+    written.extend(source.export_extras(input_path, output_path, options)?);
+    Ok(Converted { files: written, ..Converted::default() })
+}
+
+/// Writes a folder of frames in the source format as a video, ordered by the
+/// number in each file name so `shot_9` comes before `shot_10`.
+fn convert_folder_to_video(
+    source: &dyn Converter,
+    input_dir: &Path,
+    output_path: &Path,
+    to_format: &str,
+    options: &ConversionOptions,
+    on_progress: &dyn Fn(Progress),
+) -> Result<Converted, ConversionError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(input_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| source.info().matches(&ext.to_string_lossy())))
+        .collect();
+    if paths.is_empty() {
+        return Err(ConversionError::ConversionFailed(format!(
+            "No {} frames found in {}",
+            source.info().name,
+            input_dir.to_string_lossy()
+        )));
+    }
+    paths.sort_by_cached_key(|path| (frame_number(path), path.clone()));
+
+    let frames = paths
+        .iter()
+        .map(|path| source.decode(path, options).and_then(|frame| video_frame(frame, options)));
+    super::video::transcoder::encode_frames(frames, options.sequence.frame_rate, &output_path.to_string_lossy(), to_format, on_progress)?;
+    Ok(Converted { files: vec![output_path.to_path_buf()], ..Converted::default() })
+}
+
+/// Extracts frames from a video and writes each through `target`, named from
+/// the sequence template next to `output_path`. Extraction and writing each
+/// count for half the progress.
+fn export_video_frames(
+    input_path: &str,
+    target: &dyn Converter,
+    output_path: &Path,
+    options: &ConversionOptions,
+    on_progress: &dyn Fn(Progress),
+) -> Result<Converted, ConversionError> {
+    let sequence = &options.sequence;
+    // Only a single timestamp is sure to give one frame, so anything else
+    // needs numbered names; checked now rather than after extracting.
+    if sequence.timestamps.len() != 1 && !sequence.name_template.contains("{n") {
+        return Err(ConversionError::ConversionFailed("The frame name template needs {n} to number the frames".to_string()));
+    }
+    let name = Path::new(input_path).file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let extension = output_path
+        .extension()
+        .map_or_else(|| target.info().id.to_string(), |ext| ext.to_string_lossy().to_string());
+
+    let frame_dir = std::env::temp_dir().join(format!("cnvrt_frames_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&frame_dir)?;
+    let extract_progress = |p: Progress| on_progress(p.within(0.0, 0.5));
+    let result = super::video::transcoder::extract_frames(input_path, &frame_dir, sequence.every_nth.max(1), &sequence.timestamps, &extract_progress)
+        .and_then(|frames| {
+            frames
+                .iter()
+                .enumerate()
+                .map(|(index, frame)| {
+                    let img = transform::apply(read_image(frame, options)?, &options.transform)?;
+                    let img = depth::reduce(img, target.output_bit_depth(options), &options.bit_depth);
+                    let file_name = format!("{}.{}", utils::frame_name(&sequence.name_template, &name, index + 1), extension);
+                    let path = output_path.with_file_name(file_name);
+                    target.encode(&img, &path, options)?;
+                    on_progress(Progress { fraction: (index + 1) as f32 / frames.len() as f32, ..Progress::default() }.within(0.5, 0.5));
+                    Ok(path)
+                })
+                .collect::<Result<Vec<_>, ConversionError>>()
+        });
+
+    let _ = std::fs::remove_dir_all(&frame_dir);
+    Ok(Converted { files: result?, ..Converted::default() })
+}
+
+/// Tone maps, transforms and flattens a frame for video, which has no alpha.
+fn video_frame(frame: DynamicImage, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+    let frame = transform::apply(tonemap::to_display(frame, &options.tone_map), &options.transform)?;
+    Ok(transparency::apply(frame, false, &options.transparency))
+}
+
+/// The last number in a file name.
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end].rfind(|c: char| !c.is_ascii_digit()).map_or(0, |at| at + 1);
+    stem[start..end].parse().ok()
+}
+
+fn write_pages(pages: &[DynamicImage], target: &dyn Converter, output_path: &Path, options: &ConversionOptions) -> Result<Vec<PathBuf>, ConversionError> {
+    if pages.len() == 1 {
+        target.encode(&pages[0], output_path, options)?;
+        return Ok(vec![output_path.to_path_buf()]);
+    }
+
+    if options.multi_page == MultiPageMode::MultiPageTiff && target.info().id == "tiff" {
+        utils::write_tiff(pages, output_path, options.tiff.compression)?;
+        return Ok(vec![output_path.to_path_buf()]);
+    }
+
+    let mut written = Vec::with_capacity(pages.len());
+    for (index, page) in pages.iter().enumerate() {
+        let path = utils::numbered_path(output_path, index, pages.len());
+        target.encode(page, &path, options)?;
+        written.push(path);
+    }
+    Ok(written)
+}
@@ -0,0 +1,348 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use super::registry::registry;
+use crate::models::ConversionError;
+
+/// How many leading bytes are inspected when sniffing a file.
+const HEADER_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    /// Weak signature shared with other data (e.g. PCX, bare MPEG-TS sync bytes).
+    Low,
+    /// Short or loosely anchored signature (e.g. BMP, ICO, SVG with an XML prolog).
+    Medium,
+    /// Unambiguous magic number at a fixed offset.
+    High,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedFormat {
+    /// Registry id of the detected format.
+    pub format: &'static str,
+    pub confidence: Confidence,
+}
+
+impl DetectedFormat {
+    fn new(format: &'static str, confidence: Confidence) -> Option<Self> {
+        Some(DetectedFormat { format, confidence })
+    }
+}
+
+/// Sniffs the format of the file at `path` from its header (and, for TGA, its footer).
+pub fn detect_file(path: &Path) -> Result<Option<DetectedFormat>, ConversionError> {
+    let mut file = File::open(path)?;
+
+    let file_len = file.metadata()?.len();
+
+    // TGA has no leading magic; version 2 files end with a fixed footer. Its
+    // header can look like an ICO or CUR one, so this is checked first.
+    let mut footer = [0u8; 18];
+    if file.seek(SeekFrom::End(-18)).is_ok() && file.read_exact(&mut footer).is_ok() && &footer[..16] == b"TRUEVISION-XFILE" {
+        return Ok(DetectedFormat::new("tga", Confidence::High));
+    }
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    file.seek(SeekFrom::Start(0))?;
+    file.by_ref().take(HEADER_LEN as u64).read_to_end(&mut header)?;
+
+    Ok(detect_header(&header, Some(file_len)))
+}
+
+/// Sniffs a format from the leading bytes of a file.
+pub fn detect_bytes(header: &[u8]) -> Option<DetectedFormat> {
+    detect_header(header, None)
+}
+
+/// [`detect_bytes`], with the file's length when it is known.
+fn detect_header(header: &[u8], file_len: Option<u64>) -> Option<DetectedFormat> {
+    use Confidence::*;
+
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let format = if is_animated_png(header) { "apng" } else { "png" };
+        return DetectedFormat::new(format, High);
+    }
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return DetectedFormat::new("jpg", High);
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return DetectedFormat::new("gif", High);
+    }
+    if header.len() >= 12 && &header[..4] == b"RIFF" {
+        match &header[8..12] {
+            b"WEBP" => return DetectedFormat::new("webp", High),
+            b"AVI " => return DetectedFormat::new("avi", High),
+            _ => {}
+        }
+    }
+    if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") || header.starts_with(b"II+\0") || header.starts_with(b"MM\0+") {
+        return DetectedFormat::new("tiff", High);
+    }
+    if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        return detect_isobmff(header);
+    }
+    if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return detect_ebml(header);
+    }
+    if header.starts_with(b"SIMPLE  =") {
+        return DetectedFormat::new("fits", High);
+    }
+    if header.len() >= 132 && &header[128..132] == b"DICM" {
+        return DetectedFormat::new("dcm", High);
+    }
+    if header.len() >= 6 && &header[..4] == b"8BPS" {
+        let format = if u16::from_be_bytes([header[4], header[5]]) == 2 { "psb" } else { "psd" };
+        return DetectedFormat::new(format, High);
+    }
+    if find(&header[..header.len().min(1024)], b"%PDF-").is_some() {
+        return DetectedFormat::new("pdf", High);
+    }
+    if header.starts_with(b"DDS ") {
+        return DetectedFormat::new("dds", High);
+    }
+    if header.starts_with(&[0x76, 0x2F, 0x31, 0x01]) {
+        return DetectedFormat::new("exr", High);
+    }
+    if header.starts_with(b"OggS") {
+        return DetectedFormat::new("ogv", High);
+    }
+    if header.starts_with(b"FLV\x01") {
+        return DetectedFormat::new("flv", High);
+    }
+    if header.starts_with(&[0x00, 0x00, 0x01, 0xBA]) || header.starts_with(&[0x00, 0x00, 0x01, 0xB3]) {
+        return DetectedFormat::new("mpg", High);
+    }
+    if let Some(detected) = detect_svg(header) {
+        return Some(detected);
+    }
+    if header.starts_with(b"BM") && header.len() >= 14 {
+        return DetectedFormat::new("bmp", Medium);
+    }
+    if header.starts_with(&[0x00, 0x00, 0x01, 0x00]) && is_icon_directory(header, file_len) {
+        return DetectedFormat::new("ico", Medium);
+    }
+    if header.starts_with(&[0x00, 0x00, 0x02, 0x00]) && is_icon_directory(header, file_len) {
+        return DetectedFormat::new("cur", Medium);
+    }
+    if header.len() >= 3 && header[0] == b'P' && (b'1'..=b'6').contains(&header[1]) && header[2].is_ascii_whitespace() {
+        return DetectedFormat::new("ppm", Medium);
+    }
+    if header.len() > 188 && header[0] == 0x47 && header[188] == 0x47 {
+        return DetectedFormat::new("ts", Low);
+    }
+    if header.len() >= 4 && header[0] == 0x0A && header[1] <= 5 && header[2] == 1 && matches!(header[3], 1 | 2 | 4 | 8) {
+        return DetectedFormat::new("pcx", Low);
+    }
+
+    None
+}
+
+/// Picks the format a file should be decoded as: the sniffed format when it is
+/// trustworthy, otherwise the format the caller declared.
+pub fn resolve_format(path: &Path, declared: &str) -> String {
+    let declared_known = registry().find(declared).is_some();
+
+    match detect_file(path) {
+        Ok(Some(detected)) if detected.confidence >= Confidence::Medium || !declared_known => {
+            // Keep the caller's spelling when it already names the same format.
+            match registry().find(declared) {
+                Some(c) if c.info().matches(detected.format) => declared.to_string(),
+                _ => detected.format.to_string(),
+            }
+        }
+        _ => declared.to_string(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// An APNG carries an `acTL` chunk ahead of its first `IDAT`.
+fn is_animated_png(header: &[u8]) -> bool {
+    let mut pos = 8;
+    while pos + 8 <= header.len() {
+        let len = u32::from_be_bytes([header[pos], header[pos + 1], header[pos + 2], header[pos + 3]]) as usize;
+        match &header[pos + 4..pos + 8] {
+            b"acTL" => return true,
+            b"IDAT" => return false,
+            _ => pos += 12 + len,
+        }
+    }
+    false
+}
+
+/// ICO and CUR headers are also how an uncompressed TGA without an image ID
+/// starts, so the first directory entry must point at a PNG or BMP image
+/// that lies within the file.
+fn is_icon_directory(header: &[u8], file_len: Option<u64>) -> bool {
+    let Some(entry) = header.get(6..22) else { return false };
+    let count = u16::from_le_bytes([header[4], header[5]]) as u64;
+    let size = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+    let offset = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as u64;
+    if count == 0 || size == 0 || offset < 6 + 16 * count || file_len.is_some_and(|len| offset + size > len) {
+        return false;
+    }
+    // BMP entries start with a BITMAPINFOHEADER, whose first field is its size.
+    let payload = header.get(offset as usize..).unwrap_or_default();
+    payload.starts_with(b"\x89PNG\r\n\x1a\n") || payload.starts_with(&40u32.to_le_bytes())
+}
+
+/// `ftyp` brands of MP4 video.
+const MP4_BRANDS: &[&[u8]] = &[
+    b"isom", b"iso2", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"avc1", b"M4V ", b"M4VH", b"M4VP", b"mmp4", b"dash", b"MSNV",
+];
+
+/// ISO base media files: HEIF/AVIF images and MP4/QuickTime video, told apart by `ftyp` brands.
+fn detect_isobmff(header: &[u8]) -> Option<DetectedFormat> {
+    let box_len = (u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize).clamp(12, header.len());
+    let major = &header[8..12];
+    let compatible: Vec<&[u8]> = header[16.min(box_len)..box_len].chunks_exact(4).collect();
+    let has = |brands: &[&[u8]]| brands.contains(&major) || compatible.iter().any(|b| brands.contains(b));
+
+    if has(&[b"avif", b"avis"]) {
+        return DetectedFormat::new("avif", Confidence::High);
+    }
+    if has(&[b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1"]) {
+        return DetectedFormat::new("heic", Confidence::High);
+    }
+    if major == b"qt  " {
+        return DetectedFormat::new("mov", Confidence::High);
+    }
+    if has(MP4_BRANDS) {
+        return DetectedFormat::new("mp4", Confidence::High);
+    }
+    // Other brands (CR3 raw, M4A audio, 3GP, ...) share the container but
+    // aren't necessarily video, so the declared format wins.
+    DetectedFormat::new("mp4", Confidence::Low)
+}
+
+/// Matroska and WebM share the EBML header and differ only in the DocType element.
+fn detect_ebml(header: &[u8]) -> Option<DetectedFormat> {
+    let window = &header[..header.len().min(64)];
+    match find(window, &[0x42, 0x82]) {
+        Some(pos) if window.get(pos + 3..pos + 7) == Some(b"webm") => DetectedFormat::new("webm", Confidence::High),
+        Some(_) => DetectedFormat::new("mkv", Confidence::High),
+        None => DetectedFormat::new("mkv", Confidence::Medium),
+    }
+}
+
+fn detect_svg(header: &[u8]) -> Option<DetectedFormat> {
+    let text = header.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(header);
+    let start = text.iter().position(|b| !b.is_ascii_whitespace())?;
+    let text = &text[start..];
+
+    if text.starts_with(b"<svg") {
+        return DetectedFormat::new("svg", Confidence::High);
+    }
+    if (text.starts_with(b"<?xml") || text.starts_with(b"<!DOCTYPE") || text.starts_with(b"<!--")) && find(text, b"<svg").is_some() {
+        return DetectedFormat::new("svg", Confidence::Medium);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let len = 16 + 4 * compatible.len() as u32;
+        let mut header = len.to_be_bytes().to_vec();
+        header.extend_from_slice(b"ftyp");
+        header.extend_from_slice(major);
+        header.extend_from_slice(&[0; 4]);
+        for brand in compatible {
+            header.extend_from_slice(*brand);
+        }
+        header
+    }
+
+    fn detect(header: &[u8]) -> Option<(&'static str, Confidence)> {
+        detect_bytes(header).map(|d| (d.format, d.confidence))
+    }
+
+    #[test]
+    fn isobmff_brands() {
+        assert_eq!(detect(&ftyp(b"heic", &[b"mif1"])), Some(("heic", Confidence::High)));
+        assert_eq!(detect(&ftyp(b"mif1", &[b"avif"])), Some(("avif", Confidence::High)));
+        assert_eq!(detect(&ftyp(b"qt  ", &[])), Some(("mov", Confidence::High)));
+        assert_eq!(detect(&ftyp(b"isom", &[b"avc1"])), Some(("mp4", Confidence::High)));
+        assert_eq!(detect(&ftyp(b"mp42", &[])), Some(("mp4", Confidence::High)));
+    }
+
+    #[test]
+    fn unknown_isobmff_brands_are_low_confidence() {
+        assert_eq!(detect(&ftyp(b"crx ", &[])), Some(("mp4", Confidence::Low)));
+        assert_eq!(detect(&ftyp(b"M4A ", &[])), Some(("mp4", Confidence::Low)));
+        assert_eq!(detect(&ftyp(b"3gp4", &[])), Some(("mp4", Confidence::Low)));
+    }
+
+    #[test]
+    fn magic_numbers() {
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n\0\0\0\0IDAT"), Some(("png", Confidence::High)));
+        assert_eq!(detect(&[0xFF, 0xD8, 0xFF, 0xE0]), Some(("jpg", Confidence::High)));
+        assert_eq!(detect(b"GIF89a"), Some(("gif", Confidence::High)));
+        assert_eq!(detect(b"RIFF\0\0\0\0WEBPVP8 "), Some(("webp", Confidence::High)));
+        assert_eq!(detect(b"II*\0"), Some(("tiff", Confidence::High)));
+        assert_eq!(detect(b"8BPS\0\x02"), Some(("psb", Confidence::High)));
+        assert_eq!(detect(b"SIMPLE  =                    T"), Some(("fits", Confidence::High)));
+        assert_eq!(detect(b"BM\0\0\0\0\0\0\0\0\0\0\0\0"), Some(("bmp", Confidence::Medium)));
+        assert_eq!(detect(b"hello"), None);
+    }
+
+    fn icon(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut file = vec![0, 0, kind, 0, 1, 0, 16, 16, 0, 0, 1, 0, 32, 0];
+        file.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        file.extend_from_slice(&22u32.to_le_bytes());
+        file.extend_from_slice(payload);
+        file
+    }
+
+    #[test]
+    fn icon_directories() {
+        let bmp = [40, 0, 0, 0, 16, 0, 0, 0];
+        assert_eq!(detect(&icon(1, b"\x89PNG\r\n\x1a\n")), Some(("ico", Confidence::Medium)));
+        assert_eq!(detect(&icon(2, &bmp)), Some(("cur", Confidence::Medium)));
+        assert_eq!(detect(&icon(1, b"not an image")), None);
+        assert_eq!(detect_header(&icon(2, &bmp), Some(24)).map(|d| d.format), None);
+    }
+
+    #[test]
+    fn uncompressed_tga_is_not_cur() {
+        let mut tga = Vec::new();
+        image::codecs::tga::TgaEncoder::new(&mut tga)
+            .disable_rle()
+            .encode(&[255; 4 * 4 * 3], 4, 4, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        assert!(tga.starts_with(&[0x00, 0x00, 0x02, 0x00]));
+        assert_eq!(detect(&tga), None);
+    }
+
+    #[test]
+    fn apng_needs_actl_before_idat() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 8]);
+        png.extend_from_slice(b"acTL");
+        png.extend_from_slice(&[0; 12]);
+        assert_eq!(detect(&png), Some(("apng", Confidence::High)));
+    }
+
+    #[test]
+    fn ebml_doctype() {
+        let mut webm = vec![0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x82, 0x84];
+        webm.extend_from_slice(b"webm");
+        assert_eq!(detect(&webm), Some(("webm", Confidence::High)));
+        let mut mkv = vec![0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x82, 0x88];
+        mkv.extend_from_slice(b"matroska");
+        assert_eq!(detect(&mkv), Some(("mkv", Confidence::High)));
+    }
+
+    #[test]
+    fn svg_prolog() {
+        assert_eq!(detect(b"  <svg xmlns=\"http://www.w3.org/2000/svg\"/>"), Some(("svg", Confidence::High)));
+        assert_eq!(detect(b"<?xml version=\"1.0\"?><svg/>"), Some(("svg", Confidence::Medium)));
+    }
+}
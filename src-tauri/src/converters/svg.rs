@@ -1,50 +1,96 @@
-use image::ImageReader;
+use image::{DynamicImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
 use std::path::Path;
-use crate::models::ConversionError;
+use std::sync::{Arc, OnceLock};
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions, SvgOptions};
 
-pub fn convert_svg(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("svg", "SVG").options(&["svg"]);
+
+pub struct SvgConverter;
+
+impl Converter for SvgConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let data = std::fs::read(input_path)?;
+        rasterize(&data, input_path.parent(), &options.svg)
+    }
+}
+
+/// System fonts are scanned once and shared by every render.
+fn font_database() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            db.load_system_fonts();
+            Arc::new(db)
+        })
+        .clone()
+}
+
+pub fn rasterize(data: &[u8], resources_dir: Option<&Path>, options: &SvgOptions) -> Result<DynamicImage, ConversionError> {
+    let mut usvg_options = usvg::Options {
+        resources_dir: resources_dir.map(|dir| dir.to_path_buf()),
+        fontdb: font_database(),
+        ..usvg::Options::default()
+    };
+    if let Some(family) = &options.font_family {
+        usvg_options.font_family = family.clone();
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    let tree = usvg::Tree::from_data(data, &usvg_options)
+        .map_err(|e| ConversionError::ImageError(format!("Failed to parse SVG: {}", e)))?;
+
+    let size = tree.size();
+    let (width, height) = output_size(size.width(), size.height(), options);
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| {
+        ConversionError::ImageError(format!("Invalid SVG output size {}x{}", width, height))
+    })?;
+
+    if let Some(color) = options.background {
+        let [r, g, b, a] = color.0;
+        pixmap.fill(tiny_skia::Color::from_rgba8(r, g, b, a));
     }
 
-    Ok(())
+    let transform = tiny_skia::Transform::from_scale(
+        width as f32 / size.width(),
+        height as f32 / size.height(),
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia stores premultiplied alpha; image expects straight alpha.
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+
+    RgbaImage::from_raw(width, height, pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| ConversionError::ImageError("Failed to build image from SVG render".to_string()))
+}
+
+fn output_size(doc_width: f32, doc_height: f32, options: &SvgOptions) -> (u32, u32) {
+    let aspect = doc_width / doc_height;
+
+    match (options.width, options.height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, (w as f32 / aspect).round().max(1.0) as u32),
+        (None, Some(h)) => ((h as f32 * aspect).round().max(1.0) as u32, h),
+        (None, None) => {
+            let scale = if options.dpi > 0.0 { options.dpi / 96.0 } else { 1.0 };
+            (
+                (doc_width * scale).round().max(1.0) as u32,
+                (doc_height * scale).round().max(1.0) as u32,
+            )
+        }
+    }
 }
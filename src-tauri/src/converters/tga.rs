@@ -1,50 +1,57 @@
-use image::ImageReader;
+use image::codecs::tga::TgaEncoder;
+use image::{DynamicImage, ImageFormat, ImageReader};
 use std::path::Path;
-use crate::models::ConversionError;
+use super::registry::{Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions, TgaOrigin};
 
-pub fn convert_tga(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("tga", "TGA").aliases(&["tpic", "icb", "vda", "vst"]).options(&["tga"]).encodable().alpha();
+
+/// Offset of the image descriptor byte in the TGA header.
+const DESCRIPTOR_OFFSET: usize = 17;
+/// Descriptor bit set when rows are stored top to bottom.
+const TOP_ORIGIN_BIT: u8 = 0x20;
+
+pub struct TgaConverter;
+
+impl Converter for TgaConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
     }
 
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    /// TGA has no signature, so the format is set explicitly. The decoder
+    /// handles every origin corner, colour-mapped and RLE variants.
+    fn decode(&self, input_path: &Path, _options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        let mut reader = ImageReader::open(input_path)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to read TGA: {}", e)))?;
+        reader.set_format(ImageFormat::Tga);
+        reader.decode()
+            .map_err(|e| ConversionError::ImageError(format!("Failed to decode TGA: {}", e)))
     }
 
-    Ok(())
+    /// Writes greyscale, RGB or RGBA depending on the source. The encoder
+    /// always stores rows top-down, so bottom-left output is written flipped
+    /// with the origin bit cleared.
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let bottom_left = options.tga.origin == TgaOrigin::BottomLeft;
+        let pixels = match img.color().has_color() {
+            true if img.color().has_alpha() => DynamicImage::ImageRgba8(img.to_rgba8()),
+            true => DynamicImage::ImageRgb8(img.to_rgb8()),
+            false if img.color().has_alpha() => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+            false => DynamicImage::ImageLuma8(img.to_luma8()),
+        };
+        let pixels = if bottom_left { pixels.flipv() } else { pixels };
+
+        let mut data = Vec::new();
+        let encoder = TgaEncoder::new(&mut data);
+        let encoder = if options.tga.rle { encoder } else { encoder.disable_rle() };
+        encoder
+            .encode(pixels.as_bytes(), pixels.width(), pixels.height(), pixels.color().into())
+            .map_err(|e| ConversionError::ImageError(format!("Failed to save TGA: {}", e)))?;
+
+        if bottom_left {
+            data[DESCRIPTOR_OFFSET] &= !TOP_ORIGIN_BIT;
+        }
+        std::fs::write(output_path, data)?;
+        Ok(())
+    }
 }
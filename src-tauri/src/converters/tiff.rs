@@ -1,65 +1,120 @@
-use image::ImageReader;
+use image::{DynamicImage, ImageReader};
 use std::path::Path;
-use crate::models::ConversionError;
+use super::metadata::{self, Metadata};
+use super::registry::{self, Converter, FormatInfo};
+use super::utils;
+use crate::models::{ConversionError, ConversionOptions};
 
-pub fn convert_tiff(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+const INFO: FormatInfo = FormatInfo::image("tiff", "TIFF").aliases(&["tif"]).options(&["tiff"]).encodable().alpha().bit_depth(16);
+
+const XMP_TAG: u16 = 700;
+/// Photoshop image resources, which carry IPTC.
+const PHOTOSHOP_TAG: u16 = 34377;
+const ICC_TAG: u16 = 34675;
+const TYPE_BYTE: u16 = 1;
+const TYPE_UNDEFINED: u16 = 7;
+
+pub struct TiffConverter;
+
+impl Converter for TiffConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
     }
 
-    let img = match ImageReader::open(input_path) {
-        Ok(reader) => {
-            match reader.decode() {
-                Ok(img) => img,
-                Err(e) => {
-                    return Err(ConversionError::ImageError(
-                        format!("Failed to decode TIFF: {}. The TIFF may use an unsupported compression codec (LZW, PackBits, JPEG, etc.). Try opening in another tool and re-saving as uncompressed.", e)
-                    ));
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        match ImageReader::open(input_path).and_then(|reader| reader.with_guessed_format()) {
+            Ok(reader) => {
+                match registry::decode_oriented(reader, options) {
+                    Ok(img) => Ok(img),
+                    Err(e) => {
+                        Err(ConversionError::ImageError(
+                            format!("Failed to decode TIFF: {}. The TIFF may use an unsupported compression codec (LZW, PackBits, JPEG, etc.). Try opening in another tool and re-saving as uncompressed.", e)
+                        ))
+                    }
                 }
             }
+            Err(e) => Err(ConversionError::ImageError(format!("Failed to open TIFF file: {}", e))),
         }
-        Err(e) => return Err(ConversionError::ImageError(format!("Failed to open TIFF file: {}", e))),
-    };
+    }
 
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ico" => {
-            img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        utils::write_tiff(std::slice::from_ref(img), output_path, options.tiff.compression)
     }
 
-    Ok(())
+    /// Adds EXIF, XMP, IPTC and ICC tags to the first directory by appending a
+    /// rewritten copy of it. The EXIF and GPS directories are appended too,
+    /// with their values relocated.
+    fn embed_metadata(&self, output_path: &Path, metadata: &Metadata) -> Result<(), ConversionError> {
+        let mut data = std::fs::read(output_path)?;
+        let malformed = || ConversionError::ImageError("Written TIFF has an unexpected layout".to_string());
+        let big_endian = match data.get(..4) {
+            Some([b'I', b'I', 42, 0]) => false,
+            Some([b'M', b'M', 0, 42]) => true,
+            _ => return Err(malformed()),
+        };
+        let u16_at = |data: &[u8], at: usize| {
+            let b: [u8; 2] = data.get(at..at + 2)?.try_into().ok()?;
+            Some(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+        };
+        let u32_at = |data: &[u8], at: usize| {
+            let b: [u8; 4] = data.get(at..at + 4)?.try_into().ok()?;
+            Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+        };
+        let u16_bytes = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u32_bytes = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+        let ifd = u32_at(&data, 4).ok_or_else(malformed)? as usize;
+        let count = u16_at(&data, ifd).ok_or_else(malformed)? as usize;
+        let entries_end = ifd + 2 + count * 12;
+        let next_ifd = u32_at(&data, entries_end).ok_or_else(malformed)?;
+
+        let exif_entries = metadata.exif.as_deref().map_or_else(Vec::new, |exif| metadata::tiff_entries(exif, &mut data, big_endian));
+        let added: Vec<(u16, u16, &[u8])> = [
+            (XMP_TAG, TYPE_BYTE, metadata.xmp.as_deref()),
+            (PHOTOSHOP_TAG, TYPE_UNDEFINED, metadata.iptc.as_deref()),
+            (ICC_TAG, TYPE_UNDEFINED, metadata.icc.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(tag, kind, value)| value.map(|value| (tag, kind, value)))
+        .collect();
+        if added.is_empty() && exif_entries.is_empty() {
+            return Ok(());
+        }
+
+        let replaced = |tag: Option<u16>| {
+            added.iter().any(|(added, _, _)| tag == Some(*added)) || exif_entries.iter().any(|entry| tag == u16_at(entry, 0))
+        };
+        let mut entries: Vec<[u8; 12]> = data[ifd + 2..entries_end]
+            .chunks_exact(12)
+            .map(|entry| entry.try_into().unwrap_or([0; 12]))
+            .filter(|entry| !replaced(u16_at(entry, 0)))
+            .collect();
+        entries.extend_from_slice(&exif_entries);
+
+        for (tag, kind, value) in added {
+            let mut entry = [0u8; 12];
+            entry[..2].copy_from_slice(&u16_bytes(tag));
+            entry[2..4].copy_from_slice(&u16_bytes(kind));
+            entry[4..8].copy_from_slice(&u32_bytes(value.len() as u32));
+            if value.len() <= 4 {
+                entry[8..8 + value.len()].copy_from_slice(value);
+            } else {
+                data.resize(data.len() + data.len() % 2, 0);
+                entry[8..].copy_from_slice(&u32_bytes(data.len() as u32));
+                data.extend_from_slice(value);
+            }
+            entries.push(entry);
+        }
+        entries.sort_by_key(|entry| u16_at(entry, 0));
+
+        data.resize(data.len() + data.len() % 2, 0);
+        let new_ifd = u32::try_from(data.len()).map_err(|_| malformed())?;
+        data.extend_from_slice(&u16_bytes(entries.len() as u16));
+        data.extend(entries.iter().flatten());
+        data.extend_from_slice(&u32_bytes(next_ifd));
+        data[4..8].copy_from_slice(&u32_bytes(new_ifd));
+
+        std::fs::write(output_path, data)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,45 @@
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+use crate::models::{ToneMapOperator, ToneMapOptions};
+
+/// Maps floating-point (scene-linear) images to 16-bit display values, so
+/// 16-bit targets keep the extra precision. Integer images are returned unchanged.
+pub fn to_display(img: DynamicImage, options: &ToneMapOptions) -> DynamicImage {
+    let scale = 2f32.powf(options.exposure);
+    let inverse_gamma = 1.0 / options.gamma.max(0.01);
+    let map = |v: f32| {
+        let x = (v * scale).max(0.0);
+        let mapped = match options.operator {
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::Aces => aces_filmic(x),
+            ToneMapOperator::ExposureGamma => x,
+        };
+        (mapped.clamp(0.0, 1.0).powf(inverse_gamma) * 65535.0).round() as u16
+    };
+    let alpha = |v: f32| (v.clamp(0.0, 1.0) * 65535.0).round() as u16;
+
+    match img {
+        DynamicImage::ImageRgb32F(buffer) => {
+            let data = buffer.pixels().flat_map(|p| p.0.map(map)).collect();
+            ImageBuffer::<Rgb<u16>, _>::from_raw(buffer.width(), buffer.height(), data).map_or(DynamicImage::ImageRgb32F(buffer), DynamicImage::ImageRgb16)
+        }
+        DynamicImage::ImageRgba32F(buffer) => {
+            let data = buffer.pixels().flat_map(|p| [map(p[0]), map(p[1]), map(p[2]), alpha(p[3])]).collect();
+            ImageBuffer::<Rgba<u16>, _>::from_raw(buffer.width(), buffer.height(), data).map_or(DynamicImage::ImageRgba32F(buffer), DynamicImage::ImageRgba16)
+        }
+        other => other,
+    }
+}
+
+/// Krzysztof Narkowicz's fit of the ACES filmic reference curve.
+fn aces_filmic(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// Inverse of the sRGB transfer function, for `[0, 1]` encoded values.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,137 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
+use crate::models::{ConversionError, CropRect, ResizeFilter, ResizeMode, ResizeOptions, Rotation, TransformOptions};
+
+/// Crops, resizes, rotates and flips in that order. With default options the
+/// image is returned unchanged.
+pub fn apply(img: DynamicImage, options: &TransformOptions) -> Result<DynamicImage, ConversionError> {
+    let mut img = match &options.crop {
+        Some(rect) => crop(img, rect)?,
+        None => img,
+    };
+
+    if let Some(resize_options) = &options.resize {
+        img = resize(img, resize_options, filter(options.filter))?;
+    }
+
+    img = match options.rotate {
+        Rotation::None => img,
+        Rotation::Rotate90 => img.rotate90(),
+        Rotation::Rotate180 => img.rotate180(),
+        Rotation::Rotate270 => img.rotate270(),
+    };
+    if options.flip_horizontal {
+        img = img.fliph();
+    }
+    if options.flip_vertical {
+        img = img.flipv();
+    }
+    Ok(img)
+}
+
+fn invalid(message: String) -> ConversionError {
+    ConversionError::ConversionFailed(message)
+}
+
+pub fn filter(filter: ResizeFilter) -> FilterType {
+    match filter {
+        ResizeFilter::Nearest => FilterType::Nearest,
+        ResizeFilter::Triangle => FilterType::Triangle,
+        ResizeFilter::CatmullRom => FilterType::CatmullRom,
+        ResizeFilter::Gaussian => FilterType::Gaussian,
+        ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+    }
+}
+
+fn crop(img: DynamicImage, rect: &CropRect) -> Result<DynamicImage, ConversionError> {
+    let (width, height) = img.dimensions();
+    let inside = rect.width > 0
+        && rect.height > 0
+        && rect.x.checked_add(rect.width).is_some_and(|right| right <= width)
+        && rect.y.checked_add(rect.height).is_some_and(|bottom| bottom <= height);
+    if !inside {
+        return Err(invalid(format!(
+            "Crop {}x{} at ({}, {}) does not fit inside the {}x{} image",
+            rect.width, rect.height, rect.x, rect.y, width, height
+        )));
+    }
+    Ok(img.crop_imm(rect.x, rect.y, rect.width, rect.height))
+}
+
+fn resize(img: DynamicImage, options: &ResizeOptions, filter: FilterType) -> Result<DynamicImage, ConversionError> {
+    let (width, height) = img.dimensions();
+    let requested = (options.width.filter(|w| *w > 0), options.height.filter(|h| *h > 0));
+    let both = || match requested {
+        (Some(w), Some(h)) => Ok((w, h)),
+        _ => Err(invalid(format!("{:?} resizing needs both a width and a height", options.mode))),
+    };
+    let scale_within = |max_width: Option<u32>, max_height: Option<u32>| -> Result<(u32, u32), ConversionError> {
+        let scale_x = max_width.map(|w| w as f64 / width as f64);
+        let scale_y = max_height.map(|h| h as f64 / height as f64);
+        let scale = match (scale_x, scale_y) {
+            (Some(x), Some(y)) => x.min(y),
+            (Some(s), None) | (None, Some(s)) => s,
+            (None, None) => return Err(invalid(format!("{:?} resizing needs a width or a height", options.mode))),
+        };
+        Ok(scaled(width, height, scale))
+    };
+
+    let resized = match options.mode {
+        ResizeMode::Exact => {
+            let (w, h) = both()?;
+            img.resize_exact(w, h, filter)
+        }
+        ResizeMode::Fit => {
+            let (w, h) = scale_within(requested.0, requested.1)?;
+            img.resize_exact(w, h, filter)
+        }
+        ResizeMode::Fill => {
+            let (box_width, box_height) = both()?;
+            let (w, h) = scale_within(Some(box_width), Some(box_height))?;
+            pad(&img.resize_exact(w, h, filter), box_width, box_height)
+        }
+        ResizeMode::Cover => {
+            let (w, h) = both()?;
+            img.resize_to_fill(w, h, filter)
+        }
+        ResizeMode::Max => {
+            let (w, h) = scale_within(requested.0, requested.1)?;
+            if w >= width && h >= height {
+                return Ok(img);
+            }
+            img.resize_exact(w, h, filter)
+        }
+        ResizeMode::Percent => {
+            let percent = options.percent.filter(|p| p.is_finite() && *p > 0.0)
+                .ok_or_else(|| invalid("Percent resizing needs a positive percentage".to_string()))?;
+            let (w, h) = scaled(width, height, percent as f64 / 100.0);
+            img.resize_exact(w, h, filter)
+        }
+    };
+    Ok(resized)
+}
+
+pub fn scaled(width: u32, height: u32, scale: f64) -> (u32, u32) {
+    let side = |v: u32| ((v as f64 * scale).round() as u32).max(1);
+    (side(width), side(height))
+}
+
+/// Centres the image on a transparent `width` x `height` canvas, keeping
+/// 16-bit and float samples.
+fn pad(img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let x = (width - img.width()) / 2;
+    let y = (height - img.height()) / 2;
+    match img {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => centred(&img.to_rgba32f(), width, height, x, y).into(),
+        DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => {
+            centred(&img.to_rgba16(), width, height, x, y).into()
+        }
+        _ => centred(&img.to_rgba8(), width, height, x, y).into(),
+    }
+}
+
+fn centred<P: Pixel>(img: &ImageBuffer<P, Vec<P::Subpixel>>, width: u32, height: u32, x: u32, y: u32) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::new(width, height);
+    image::imageops::replace(&mut canvas, img, x as i64, y as i64);
+    canvas
+}
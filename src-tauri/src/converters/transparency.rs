@@ -0,0 +1,60 @@
+use image::{DynamicImage, GenericImageView, Rgb, Rgb32FImage};
+use crate::models::{Color, TransparencyOptions};
+
+/// Trims transparent borders if requested, then keeps alpha only when the
+/// target supports it and the user wants it; otherwise flattens onto the
+/// background colour. Opaque images pass through untouched.
+pub fn apply(img: DynamicImage, target_has_alpha: bool, options: &TransparencyOptions) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return img;
+    }
+
+    let img = if options.trim { trim(img) } else { img };
+    if options.keep && target_has_alpha {
+        return img;
+    }
+    flatten(&img, options.background)
+}
+
+/// Crops to the bounding box of pixels that aren't fully transparent. An
+/// entirely transparent image is left as is.
+fn trim(img: DynamicImage) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for (x, y, pixel) in img.pixels() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        bounds = Some(match bounds {
+            None => (x, y, x, y),
+            Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x), bottom.max(y)),
+        });
+    }
+
+    match bounds {
+        Some((left, top, right, bottom)) if (left, top, right, bottom) != (0, 0, width - 1, height - 1) => {
+            img.crop_imm(left, top, right - left + 1, bottom - top + 1)
+        }
+        _ => img,
+    }
+}
+
+/// Composites over an opaque background, keeping the source's bit depth.
+/// The background's own alpha is ignored, as the result is always opaque.
+fn flatten(img: &DynamicImage, background: Color) -> DynamicImage {
+    let rgba = img.to_rgba32f();
+    let [r, g, b, _] = background.0.map(|c| c as f32 / 255.0);
+
+    let flat = Rgb32FImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let p = rgba.get_pixel(x, y);
+        let a = p[3].clamp(0.0, 1.0);
+        Rgb([p[0] * a + r * (1.0 - a), p[1] * a + g * (1.0 - a), p[2] * a + b * (1.0 - a)])
+    });
+
+    match img {
+        DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgb32F(flat),
+        DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgba16(_) => DynamicImage::ImageRgb16(DynamicImage::ImageRgb32F(flat).to_rgb16()),
+        _ => DynamicImage::ImageRgb8(DynamicImage::ImageRgb32F(flat).to_rgb8()),
+    }
+}
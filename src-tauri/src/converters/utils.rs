@@ -1,108 +1,163 @@
-use image::ImageReader;
-use std::path::Path;
-use crate::models::ConversionError;
+use image::DynamicImage;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use tiff::encoder::{colortype, Compression, DeflateLevel, TiffEncoder};
+use super::registry::{registry, FormatKind};
+use crate::models::{ConversionError, TiffCompression};
 
 pub fn get_supported_formats(from_format: &str) -> Vec<String> {
-    let format_lower = from_format.to_lowercase();
-    
-    // Check if it's a video format
-    if is_video_format(&format_lower) {
-        vec![
-            "mp4".to_string(),
-            "mkv".to_string(),
-            "mov".to_string(),
-            "webm".to_string(),
-            "avi".to_string(),
-            "flv".to_string(),
-            "mpg".to_string(),
-            "ts".to_string(),
-            "ogv".to_string(),
-        ]
-    } else {
-        // Image formats
-        vec![
-            "png".to_string(),
-            "jpg".to_string(),
-            "jpeg".to_string(),
-            "webp".to_string(),
-            "bmp".to_string(),
-            "gif".to_string(),
-            "tiff".to_string(),
-            "ico".to_string(),
-            "ppm".to_string(),
-            "pgm".to_string(),
-            "pbm".to_string(),
-        ]
+    match registry().find(from_format).map(|c| c.info()) {
+        // Sequences can become video, and videos animations or image sequences.
+        Some(info) if info.sequence || info.kind == FormatKind::Video => {
+            let mut formats = registry().output_extensions(FormatKind::Image);
+            formats.extend(registry().output_extensions(FormatKind::Video));
+            formats
+        }
+        Some(info) => registry().output_extensions(info.kind),
+        None => Vec::new(),
     }
 }
 
 pub fn is_image_format(format: &str) -> bool {
-    matches!(
-        format.to_lowercase().as_str(),
-        "png" | "jpg" | "jpeg" | "webp" | "bmp" | "gif" | "tiff" | "ico" | "ppm" | "pgm" | "pbm"
-        | "avif" | "heic" | "heif" | "tga" | "dds" | "apng" | "cur" | "exr" | "svg" | "pdf" | "psd" | "psb" | "fits" | "dcm" | "pcx" | "jfif"
-    )
+    registry()
+        .find(format)
+        .is_some_and(|c| c.info().kind == FormatKind::Image)
 }
 
 pub fn is_video_format(format: &str) -> bool {
-    matches!(
-        format.to_lowercase().as_str(),
-        "mp4" | "m4v" | "mkv" | "mov" | "webm" | "avi" | "flv" | "mpg" | "mpeg" | "ts" | "m2ts" | "mts" | "ogv" | "ogg"
-    )
+    registry()
+        .find(format)
+        .is_some_and(|c| c.info().kind == FormatKind::Video)
 }
 
-/// Generic image converter that works for any image format pair
-pub fn convert_any_image_format(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
+/// Parses a 1-based selection such as `"1-3,5,8-"` into sorted, de-duplicated
+/// 0-based indices below `count`. Pages past the end and reversed ranges are
+/// errors rather than being dropped.
+pub fn parse_index_ranges(spec: &str, count: usize) -> Result<Vec<usize>, ConversionError> {
+    let invalid = |part: &str, reason: String| ConversionError::ConversionFailed(format!("Invalid page range: {} ({})", part, reason));
+    let pages = if count == 1 { "1 page".to_string() } else { format!("{} pages", count) };
+
+    let mut indices = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n >= 1)
+                .ok_or_else(|| invalid(part, "pages are numbered from 1".to_string()))
+        };
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => {
+                let start = if start.trim().is_empty() { 1 } else { parse(start)? };
+                let end = if end.trim().is_empty() { count } else { parse(end)? };
+                (start, end)
+            }
+            None => {
+                let page = parse(part)?;
+                (page, page)
+            }
+        };
+        if start > end {
+            return Err(invalid(part, "the range is reversed".to_string()));
+        }
+        if end > count {
+            return Err(invalid(part, format!("there are only {}", pages)));
+        }
+        indices.extend((start..=end).map(|page| page - 1));
     }
 
-    // Auto-detect input format and decode
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(format!("Failed to read image: {}", e)))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(format!("Failed to decode image: {}", e)))?;
+    indices.sort_unstable();
+    indices.dedup();
+    Ok(indices)
+}
+
+/// `photo.png` -> `photo_001.png`, padding to at least three digits.
+pub fn numbered_path(path: &Path, index: usize, total: usize) -> PathBuf {
+    let width = total.to_string().len().max(3);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:0width$}.{}", stem, index + 1, ext.to_string_lossy(), width = width),
+        None => format!("{}_{:0width$}", stem, index + 1, width = width),
+    };
+    path.with_file_name(name)
+}
 
-    let output_path = Path::new(output_path);
-    
-    // Save with specified output format
-    match target_format.to_lowercase().as_str() {
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save JPEG: {}", e)))?;
-        },
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save PNG: {}", e)))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save WebP: {}", e)))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save BMP: {}", e)))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save GIF: {}", e)))?;
-        },
-        "tiff" | "tif" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save TIFF: {}", e)))?;
-        },
-        "ico" => {
-            img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save ICO: {}", e)))?;
-        },
-        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(format!("Failed to save PNM: {}", e)))?;
-        },
-        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
+/// Fills a frame name template: `{name}` becomes `name` and `{n}` the
+/// frame number, zero-padded to the width given as `{n:05}`. Anything else
+/// in braces is kept as written.
+pub fn frame_name(template: &str, name: &str, number: usize) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|len| start + len) else { break };
+        out.push_str(&rest[..start]);
+        let field = &rest[start + 1..end];
+        match field {
+            "name" => out.push_str(name),
+            "n" => out.push_str(&number.to_string()),
+            _ => match field.strip_prefix("n:").and_then(|width| width.parse::<usize>().ok()) {
+                Some(width) => out.push_str(&format!("{:0width$}", number, width = width)),
+                None => out.push_str(&rest[start..=end]),
+            },
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Writes one TIFF directory per image, keeping 8-bit, 16-bit and float
+/// samples as they are. Grey with alpha is widened to RGBA, which TIFF readers
+/// handle more consistently.
+pub fn write_tiff(images: &[DynamicImage], output_path: &Path, compression: TiffCompression) -> Result<(), ConversionError> {
+    let compression = match compression {
+        TiffCompression::None => Compression::Uncompressed,
+        TiffCompression::Lzw => Compression::Lzw,
+        TiffCompression::Deflate => Compression::Deflate(DeflateLevel::Balanced),
+        TiffCompression::PackBits => Compression::Packbits,
+    };
+
+    let file = BufWriter::new(File::create(output_path)?);
+    let mut encoder = TiffEncoder::new(file)
+        .map_err(|e| ConversionError::ImageError(format!("Failed to create TIFF: {}", e)))?
+        .with_compression(compression);
+
+    for img in images {
+        let (width, height) = (img.width(), img.height());
+        let result = match img {
+            DynamicImage::ImageLuma8(buf) => encoder.write_image::<colortype::Gray8>(width, height, buf.as_raw()),
+            DynamicImage::ImageLuma16(buf) => encoder.write_image::<colortype::Gray16>(width, height, buf.as_raw()),
+            DynamicImage::ImageRgb8(buf) => encoder.write_image::<colortype::RGB8>(width, height, buf.as_raw()),
+            DynamicImage::ImageRgb16(buf) => encoder.write_image::<colortype::RGB16>(width, height, buf.as_raw()),
+            DynamicImage::ImageRgb32F(buf) => encoder.write_image::<colortype::RGB32Float>(width, height, buf.as_raw()),
+            DynamicImage::ImageRgba32F(buf) => encoder.write_image::<colortype::RGBA32Float>(width, height, buf.as_raw()),
+            DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgba16(_) => {
+                encoder.write_image::<colortype::RGBA16>(width, height, img.to_rgba16().as_raw())
+            }
+            _ => encoder.write_image::<colortype::RGBA8>(width, height, img.to_rgba8().as_raw()),
+        };
+        result.map_err(|e| ConversionError::ImageError(format!("Failed to write TIFF page: {}", e)))?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_ranges() {
+        assert_eq!(parse_index_ranges("1-2,5,4-", 6).unwrap(), vec![0, 1, 3, 4, 5]);
+        assert_eq!(parse_index_ranges("-2,2", 3).unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn out_of_range_pages_are_errors() {
+        for spec in ["2-9", "1,5", "5", "3-1", "0", "x", "4-"] {
+            let error = parse_index_ranges(spec, 3).unwrap_err().to_string();
+            assert!(error.contains("Invalid page range"), "{}: {}", spec, error);
+        }
+    }
+}
@@ -250,6 +250,7 @@ fn extract_tar_from_file(file_path: &Path, target_dir: &Path) -> Result<(), Stri
             #[cfg(not(windows))]
             {
                 use std::fs;
+                use std::os::unix::fs::PermissionsExt;
                 let perms = fs::Permissions::from_mode(0o755);
                 fs::set_permissions(&target_path, perms)
                     .map_err(|e| format!("Failed to set permissions: {}", e))?;
@@ -1,3 +1,5 @@
+use crate::converters::registry::{Converter, FormatInfo};
+
 #[derive(Debug, Clone)]
 pub struct VideoFormat {
     pub extension: String,
@@ -121,3 +123,31 @@ pub fn get_video_codec_for_format(format: &str) -> String {
     }
     .to_string()
 }
+
+/// Registry entry for a video container; conversion itself goes through the transcoder.
+pub struct VideoContainerConverter {
+    info: FormatInfo,
+}
+
+impl Converter for VideoContainerConverter {
+    fn info(&self) -> &FormatInfo {
+        &self.info
+    }
+}
+
+pub fn converters() -> Vec<VideoContainerConverter> {
+    vec![
+        FormatInfo::video("mp4", "MP4").aliases(&["m4v"]),
+        FormatInfo::video("mkv", "Matroska"),
+        FormatInfo::video("mov", "QuickTime"),
+        FormatInfo::video("webm", "WebM"),
+        FormatInfo::video("avi", "AVI"),
+        FormatInfo::video("flv", "FLV"),
+        FormatInfo::video("mpg", "MPEG").aliases(&["mpeg"]),
+        FormatInfo::video("ts", "MPEG-TS").aliases(&["m2ts", "mts"]),
+        FormatInfo::video("ogv", "Ogg Video").aliases(&["ogg"]),
+    ]
+    .into_iter()
+    .map(|info| VideoContainerConverter { info })
+    .collect()
+}
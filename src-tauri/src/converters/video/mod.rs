@@ -1,3 +1,4 @@
 pub mod transcoder;
 pub mod formats;
 pub mod ffmpeg_manager;
+pub mod progress;
@@ -0,0 +1,103 @@
+//! Running FFmpeg jobs with `-progress` output, which reports the current
+//! output time, frame rate and speed as `key=value` lines on stdout.
+
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use crate::models::ConversionError;
+
+/// How far a running FFmpeg job has got.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    /// 0.0 to 1.0; stays 0 when the job's length isn't known.
+    pub fraction: f32,
+    /// Frames encoded per second.
+    pub fps: Option<f32>,
+    /// Encoding speed relative to playback, e.g. 2.0 for twice real time.
+    pub speed: Option<f32>,
+}
+
+impl Progress {
+    /// Time left, going by how long the job has taken to get this far.
+    pub fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        (self.fraction > 0.0).then(|| elapsed.mul_f32((1.0 - self.fraction.min(1.0)) / self.fraction))
+    }
+
+    /// Maps this onto the part of a larger job running from `start` for `share` of it.
+    pub fn within(self, start: f32, share: f32) -> Progress {
+        Progress { fraction: start + self.fraction * share, ..self }
+    }
+}
+
+/// An FFmpeg command that writes progress to stdout. The options are global,
+/// so they go before any input or output.
+pub fn command(ffmpeg_path: &Path) -> Command {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::null());
+    cmd.arg("-nostats");
+    cmd.arg("-progress").arg("pipe:1");
+    cmd
+}
+
+/// Runs a [`command`], calling `on_progress` each time FFmpeg reports.
+/// `total` is the length of the output; without it only speed and frame
+/// rate are reported.
+pub fn run(mut cmd: Command, total: Option<Duration>, failure: &str, on_progress: &dyn Fn(Progress)) -> Result<(), ConversionError> {
+    let mut child = cmd.spawn().map_err(|e| ConversionError::ImageError(format!("Failed to run FFmpeg: {}", e)))?;
+
+    if let Some(stdout) = child.stdout.take() {
+        let total = total.filter(|total| !total.is_zero());
+        let mut current = Progress::default();
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let value = value.trim();
+            match key {
+                // Despite its name, out_time_ms is also in microseconds.
+                "out_time_us" | "out_time_ms" => {
+                    if let (Ok(micros), Some(total)) = (value.parse::<u64>(), total) {
+                        current.fraction = (micros as f64 / total.as_micros() as f64).clamp(0.0, 1.0) as f32;
+                    }
+                }
+                "fps" => current.fps = value.parse().ok().filter(|fps: &f32| *fps > 0.0),
+                "speed" => current.speed = value.trim_end_matches('x').parse().ok(),
+                "progress" => {
+                    if value == "end" {
+                        current.fraction = 1.0;
+                    }
+                    on_progress(current);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err(ConversionError::ImageError(failure.to_string())),
+        Err(e) => Err(ConversionError::ImageError(format!("Failed to run FFmpeg: {}", e))),
+    }
+}
+
+/// The input's length, from the `Duration:` line FFmpeg prints when it
+/// opens a file. FFmpeg exits with an error here since no output is given.
+pub fn probe_duration(ffmpeg_path: &Path, input_path: &str) -> Option<Duration> {
+    let output = Command::new(ffmpeg_path)
+        .arg("-hide_banner")
+        .arg("-i")
+        .arg(input_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .ok()?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (_, rest) = stderr.split_once("Duration: ")?;
+    let mut parts = rest.split(',').next()?.trim().split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+    let seconds = hours.parse::<f64>().ok()? * 3600.0 + minutes.parse::<f64>().ok()? * 60.0 + seconds.parse::<f64>().ok()?;
+    (seconds > 0.0).then(|| Duration::from_secs_f64(seconds))
+}
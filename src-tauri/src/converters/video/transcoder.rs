@@ -1,8 +1,11 @@
-use crate::models::ConversionError;
-use std::path::Path;
-use std::process::Command;
+use crate::models::{ConversionError, ConversionOptions, GifPalette, PaletteDither};
+use image::DynamicImage;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use super::formats;
 use super::ffmpeg_manager;
+use crate::converters::animation;
+use super::progress::{self, Progress};
 
 pub struct VideoTranscoder {
     input_path: String,
@@ -34,6 +37,11 @@ impl VideoTranscoder {
     }
 
     pub fn transcode(&self) -> Result<(), ConversionError> {
+        self.transcode_with_progress(&|_| {})
+    }
+
+    /// Transcodes, reporting progress against the input's probed duration.
+    pub fn transcode_with_progress(&self, on_progress: &dyn Fn(Progress)) -> Result<(), ConversionError> {
         let input_path = Path::new(&self.input_path);
         if !input_path.exists() {
             return Err(ConversionError::FileNotFound(
@@ -45,12 +53,9 @@ impl VideoTranscoder {
             .map_err(|e| ConversionError::ImageError(e))?;
 
         let codec = formats::get_video_codec_for_format(&self.target_format);
+        let duration = progress::probe_duration(&ffmpeg_path, &self.input_path);
         
-        let mut cmd = Command::new(ffmpeg_path);
-        
-        cmd.stdin(std::process::Stdio::null());
-        cmd.stdout(std::process::Stdio::null());
-        cmd.stderr(std::process::Stdio::null());
+        let mut cmd = progress::command(&ffmpeg_path);
         
         cmd.arg("-i").arg(&self.input_path);
         
@@ -70,21 +75,12 @@ impl VideoTranscoder {
         
         cmd.arg(&self.output_path);
 
-        match cmd.status() {
-            Ok(status) => {
-                if status.success() {
-                    Ok(())
-                } else {
-                    Err(ConversionError::ImageError(
-                        "FFmpeg transcoding failed. The file format may not be supported.".to_string()
-                    ))
-                }
-            }
-            Err(e) => Err(ConversionError::ImageError(format!(
-                "Failed to run FFmpeg: {}",
-                e
-            ))),
-        }
+        progress::run(
+            cmd,
+            duration,
+            "FFmpeg transcoding failed. The file format may not be supported.",
+            on_progress,
+        )
     }
 }
 
@@ -92,6 +88,7 @@ pub fn convert_video(
     input_path: &str,
     output_path: &str,
     target_format: &str,
+    on_progress: &dyn Fn(Progress),
 ) -> Result<(), ConversionError> {
     if !formats::is_video_format(target_format) {
         return Err(ConversionError::UnsupportedFormat(target_format.to_string()));
@@ -100,5 +97,237 @@ pub fn convert_video(
     let transcoder = VideoTranscoder::new(input_path, output_path, target_format)
         .with_preset("medium");
 
-    transcoder.transcode()
+    transcoder.transcode_with_progress(on_progress)
+}
+
+/// Frame rate for video clips turned into animations when none is requested.
+const DEFAULT_ANIMATION_FPS: f32 = 10.0;
+
+/// Cuts a clip from a video and writes it as an animated GIF, WebP or APNG.
+/// GIFs take two passes: `palettegen` picks the colours, then `paletteuse`
+/// maps the frames onto them, each counting for half the progress.
+pub fn convert_to_animation(
+    input_path: &str,
+    output_path: &str,
+    target_format: &str,
+    options: &ConversionOptions,
+    on_progress: &dyn Fn(Progress),
+) -> Result<(), ConversionError> {
+    if !Path::new(input_path).exists() {
+        return Err(ConversionError::FileNotFound(input_path.to_string()));
+    }
+    let clip = &options.clip;
+    if clip.start < 0.0 || clip.duration.is_some_and(|d| d <= 0.0) {
+        return Err(ConversionError::ConversionFailed("Clip start must be 0 or later and its duration greater than 0".to_string()));
+    }
+    let fps = options.animation.frame_rate.map(animation::check_frame_rate).transpose()?.unwrap_or(DEFAULT_ANIMATION_FPS);
+
+    let ffmpeg_path = ffmpeg_manager::ensure_ffmpeg()
+        .map_err(|e| ConversionError::ImageError(e))?;
+
+    let mut filters = format!("fps={}", fps);
+    if let Some(width) = clip.width.filter(|w| *w > 0) {
+        filters.push_str(&format!(",scale={}:-1:flags=lanczos", width));
+    }
+    // Animation loop counts are plays, with 0 looping forever.
+    let plays = options.animation.loop_count.unwrap_or(0);
+    let length = clip.duration.map(Duration::from_secs_f64).or_else(|| {
+        progress::probe_duration(&ffmpeg_path, input_path).map(|total| total.saturating_sub(Duration::from_secs_f64(clip.start)))
+    });
+
+    let command = || {
+        let mut cmd = progress::command(&ffmpeg_path);
+        cmd.arg("-y");
+        cmd.arg("-ss").arg(clip.start.to_string());
+        if let Some(duration) = clip.duration {
+            cmd.arg("-t").arg(duration.to_string());
+        }
+        cmd.arg("-i").arg(input_path);
+        cmd
+    };
+
+    match target_format.to_lowercase().as_str() {
+        "gif" => {
+            let gif = &options.gif;
+            let palette_path = std::env::temp_dir().join(format!("cnvrt_palette_{}.png", uuid::Uuid::new_v4()));
+            let (stats_mode, new_palette) = match gif.palette {
+                GifPalette::Global => ("full", 0),
+                GifPalette::PerFrame => ("single", 1),
+            };
+            let dither = match gif.dither {
+                PaletteDither::None => "none",
+                PaletteDither::FloydSteinberg => "floyd_steinberg",
+                PaletteDither::Ordered => "bayer",
+            };
+
+            let mut pass1 = command();
+            pass1.arg("-vf").arg(format!("{},palettegen=max_colors={}:stats_mode={}", filters, gif.colors.clamp(2, 256), stats_mode));
+            pass1.arg(&palette_path);
+
+            let palette_progress = |p: Progress| on_progress(p.within(0.0, 0.5));
+            let encode_progress = |p: Progress| on_progress(p.within(0.5, 0.5));
+            let result = progress::run(pass1, length, "FFmpeg failed to build the GIF palette.", &palette_progress).and_then(|_| {
+                let mut pass2 = command();
+                pass2.arg("-i").arg(&palette_path);
+                pass2.arg("-lavfi").arg(format!("{} [x]; [x][1:v] paletteuse=dither={}:new={}", filters, dither, new_palette));
+                // GIF stores repeats after the first play, with -1 for none.
+                let repeats = match plays {
+                    0 => 0,
+                    1 => -1,
+                    plays => plays as i64 - 1,
+                };
+                pass2.arg("-loop").arg(repeats.to_string());
+                pass2.arg(output_path);
+                progress::run(pass2, length, "FFmpeg failed to encode the GIF.", &encode_progress)
+            });
+
+            let _ = std::fs::remove_file(&palette_path);
+            result
+        }
+        "webp" => {
+            let mut cmd = command();
+            cmd.arg("-vf").arg(filters);
+            cmd.arg("-c:v").arg("libwebp");
+            cmd.arg("-lossless").arg(if options.webp.lossless { "1" } else { "0" });
+            cmd.arg("-q:v").arg(options.webp.quality.min(100).to_string());
+            cmd.arg("-loop").arg(plays.to_string());
+            cmd.arg("-an");
+            cmd.arg(output_path);
+            progress::run(cmd, length, "FFmpeg failed to encode the WebP animation.", on_progress)
+        }
+        "apng" => {
+            let mut cmd = command();
+            cmd.arg("-vf").arg(filters);
+            cmd.arg("-f").arg("apng");
+            cmd.arg("-plays").arg(plays.to_string());
+            cmd.arg("-an");
+            cmd.arg(output_path);
+            progress::run(cmd, length, "FFmpeg failed to encode the APNG.", on_progress)
+        }
+        other => Err(ConversionError::UnsupportedFormat(other.to_string())),
+    }
+}
+
+/// Encodes decoded frames (e.g. a multi-frame DICOM study or a folder of
+/// numbered images) as a video by staging them as numbered PNGs for FFmpeg's
+/// image sequence input. Frames are staged as they arrive, so only one is
+/// held at a time.
+pub fn encode_frames(
+    frames: impl IntoIterator<Item = Result<DynamicImage, ConversionError>>,
+    frame_rate: f32,
+    output_path: &str,
+    target_format: &str,
+    on_progress: &dyn Fn(Progress),
+) -> Result<(), ConversionError> {
+    if !formats::is_video_format(target_format) {
+        return Err(ConversionError::UnsupportedFormat(target_format.to_string()));
+    }
+
+    let ffmpeg_path = ffmpeg_manager::ensure_ffmpeg()
+        .map_err(|e| ConversionError::ImageError(e))?;
+
+    let frame_dir = std::env::temp_dir().join(format!("cnvrt_frames_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&frame_dir)?;
+
+    let result = stage_frames(frames, &frame_dir).and_then(|staged| {
+        if staged == 0 {
+            return Err(ConversionError::ConversionFailed("No frames to encode".to_string()));
+        }
+
+        let frame_rate = frame_rate.max(0.1);
+        let length = Duration::from_secs_f32(staged as f32 / frame_rate);
+        let mut cmd = progress::command(&ffmpeg_path);
+
+        cmd.arg("-y");
+        cmd.arg("-framerate").arg(frame_rate.to_string());
+        cmd.arg("-i").arg(frame_dir.join("frame_%05d.png"));
+
+        cmd.arg("-c:v").arg(formats::get_video_codec_for_format(target_format));
+        // Most encoders need even dimensions and a 4:2:0 pixel format for broad playback support.
+        cmd.arg("-vf").arg("pad=ceil(iw/2)*2:ceil(ih/2)*2");
+        cmd.arg("-pix_fmt").arg("yuv420p");
+
+        cmd.arg(output_path);
+
+        progress::run(cmd, Some(length), "FFmpeg failed to encode the frame sequence.", on_progress)
+    });
+
+    let _ = std::fs::remove_dir_all(&frame_dir);
+    result
+}
+
+/// Writes the selected frames of a video into `frame_dir` as numbered PNGs,
+/// returning their paths in order: those at `timestamps` (in seconds) when
+/// any are given, otherwise every `every_nth` frame.
+pub fn extract_frames(
+    input_path: &str,
+    frame_dir: &Path,
+    every_nth: u32,
+    timestamps: &[f64],
+    on_progress: &dyn Fn(Progress),
+) -> Result<Vec<PathBuf>, ConversionError> {
+    if !Path::new(input_path).exists() {
+        return Err(ConversionError::FileNotFound(input_path.to_string()));
+    }
+    if timestamps.iter().any(|t| *t < 0.0) {
+        return Err(ConversionError::ConversionFailed("Frame timestamps must be 0 or later".to_string()));
+    }
+
+    let ffmpeg_path = ffmpeg_manager::ensure_ffmpeg()
+        .map_err(|e| ConversionError::ImageError(e))?;
+    let command = || {
+        let mut cmd = progress::command(&ffmpeg_path);
+        cmd.arg("-y");
+        cmd
+    };
+
+    if timestamps.is_empty() {
+        let mut cmd = command();
+        cmd.arg("-i").arg(input_path);
+        if every_nth > 1 {
+            cmd.arg("-vf").arg(format!("select=not(mod(n\\,{}))", every_nth));
+            // Without this the dropped frames are filled back in to keep the frame rate.
+            cmd.arg("-fps_mode").arg("vfr");
+        }
+        cmd.arg(frame_dir.join("frame_%05d.png"));
+        let duration = progress::probe_duration(&ffmpeg_path, input_path);
+        progress::run(cmd, duration, "FFmpeg failed to extract the video frames.", on_progress)?;
+    } else {
+        // Each seek is a separate run with no known length, so progress counts frames.
+        let share = 1.0 / timestamps.len() as f32;
+        for (index, time) in timestamps.iter().enumerate() {
+            let mut cmd = command();
+            // Seeking before the input is fast and, when decoding, frame-accurate.
+            cmd.arg("-ss").arg(time.to_string());
+            cmd.arg("-i").arg(input_path);
+            cmd.arg("-frames:v").arg("1");
+            cmd.arg(frame_dir.join(format!("frame_{:05}.png", index + 1)));
+            let frame_progress = |p: Progress| on_progress(p.within(index as f32 * share, share));
+            progress::run(cmd, None, &format!("FFmpeg failed to extract the frame at {}s.", time), &frame_progress)?;
+        }
+    }
+
+    let mut frames: Vec<PathBuf> = std::fs::read_dir(frame_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    frames.sort();
+    if frames.is_empty() {
+        return Err(ConversionError::ConversionFailed("No frames were extracted from the video".to_string()));
+    }
+    Ok(frames)
+}
+
+fn stage_frames(
+    frames: impl IntoIterator<Item = Result<DynamicImage, ConversionError>>,
+    frame_dir: &Path,
+) -> Result<usize, ConversionError> {
+    let mut staged = 0;
+    for (index, frame) in frames.into_iter().enumerate() {
+        let path = frame_dir.join(format!("frame_{:05}.png", index + 1));
+        frame?.save_with_format(&path, image::ImageFormat::Png)
+            .map_err(|e| ConversionError::ImageError(format!("Failed to stage frame {}: {}", index + 1, e)))?;
+        staged += 1;
+    }
+    Ok(staged)
 }
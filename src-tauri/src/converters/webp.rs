@@ -1,54 +1,206 @@
-use image::ImageReader;
+use image::codecs::webp::{WebPDecoder, WebPEncoder};
+use image::DynamicImage;
+use std::io::Cursor;
 use std::path::Path;
-use crate::models::ConversionError;
-
-pub fn convert_webp(input_path: &str, output_path: &str, target_format: &str) -> Result<(), ConversionError> {
-    let input_path = Path::new(input_path);
-    
-    if !input_path.exists() {
-        return Err(ConversionError::FileNotFound(input_path.to_string_lossy().to_string()));
-    }
-
-    let img = ImageReader::open(input_path)
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        .decode()
-        .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-
-    let output_path = Path::new(output_path);
-    
-    match target_format.to_lowercase().as_str() {
-        "jpg" | "jpeg" => {
-            img.save_with_format(output_path, image::ImageFormat::Jpeg)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "png" => {
-            img.save_with_format(output_path, image::ImageFormat::Png)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "webp" => {
-            img.save_with_format(output_path, image::ImageFormat::WebP)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "bmp" => {
-            img.save_with_format(output_path, image::ImageFormat::Bmp)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "gif" => {
-            img.save_with_format(output_path, image::ImageFormat::Gif)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "tiff" => {
-            img.save_with_format(output_path, image::ImageFormat::Tiff)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },
-        "ico" => {
-            img.save_with_format(output_path, image::ImageFormat::Ico)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?;
-        },        "ppm" | "pgm" | "pbm" => {
-            img.save_with_format(output_path, image::ImageFormat::Pnm)
-                .map_err(|e| ConversionError::ImageError(e.to_string()))?
-        },        _ => return Err(ConversionError::UnsupportedFormat(target_format.to_string())),
-    }
-
-    Ok(())
+use super::animation::{self, Animation};
+use super::metadata::Metadata;
+use super::registry::{self, Converter, FormatInfo};
+use crate::models::{ConversionError, ConversionOptions};
+
+const INFO: FormatInfo = FormatInfo::image("webp", "WebP").options(&["webp"]).encodable().alpha().animated();
+
+const VP8X_ICC: u8 = 0x20;
+const VP8X_ALPHA: u8 = 0x10;
+const VP8X_EXIF: u8 = 0x08;
+const VP8X_XMP: u8 = 0x04;
+const VP8X_ANIMATION: u8 = 0x02;
+/// `ANMF` flag: draw the frame over the canvas without alpha-blending.
+const ANMF_NO_BLEND: u8 = 0x02;
+/// Largest frame duration `ANMF` can hold, in milliseconds.
+const MAX_DURATION: u32 = 0xFF_FFFF;
+
+pub struct WebpConverter;
+
+impl Converter for WebpConverter {
+    fn info(&self) -> &FormatInfo {
+        &INFO
+    }
+
+    fn decode(&self, input_path: &Path, options: &ConversionOptions) -> Result<DynamicImage, ConversionError> {
+        registry::read_image(input_path, options)
+    }
+
+    fn encode(&self, img: &DynamicImage, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        std::fs::write(output_path, encode_still(img, options)?)?;
+        Ok(())
+    }
+
+    fn decode_animation(&self, input_path: &Path, _options: &ConversionOptions) -> Result<Option<Animation>, ConversionError> {
+        let data = std::fs::read(input_path)?;
+        let decoder = WebPDecoder::new(Cursor::new(&data))
+            .map_err(|e| ConversionError::ImageError(format!("Failed to read WebP: {}", e)))?;
+        if !decoder.has_animation() {
+            return Ok(None);
+        }
+        let loop_count = read_chunks(&data)
+            .and_then(|chunks| chunks.into_iter().find(|(kind, _)| kind == b"ANIM"))
+            .and_then(|(_, body)| body.get(4..6))
+            .map_or(0, |count| u16::from_le_bytes([count[0], count[1]]) as u32);
+        animation::decode(decoder, loop_count).map(Some)
+    }
+
+    /// Encodes each frame as a still with the WebP options and wraps its
+    /// bitstream chunks in an `ANMF` frame covering the whole canvas.
+    fn encode_animation(&self, animation: &Animation, output_path: &Path, options: &ConversionOptions) -> Result<(), ConversionError> {
+        let (width, height) = animation.dimensions();
+        let has_alpha = animation.has_alpha();
+        let malformed = || ConversionError::ImageError("Encoded WebP frame has an unexpected layout".to_string());
+
+        let mut header = vec![VP8X_ANIMATION | if has_alpha { VP8X_ALPHA } else { 0 }, 0, 0, 0];
+        header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        // Transparent black background, then the loop count (0 is forever).
+        let mut anim = vec![0; 4];
+        anim.extend_from_slice(&(animation.loop_count.min(u16::MAX as u32) as u16).to_le_bytes());
+
+        let mut body = b"WEBP".to_vec();
+        push_chunk(&mut body, b"VP8X", &header);
+        push_chunk(&mut body, b"ANIM", &anim);
+        for frame in &animation.frames {
+            let image = if has_alpha {
+                DynamicImage::ImageRgba8(frame.image.clone())
+            } else {
+                DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(frame.image.clone()).to_rgb8())
+            };
+            let encoded = encode_still(&image, options)?;
+
+            // Offset 0,0, canvas-sized, the duration in milliseconds, and
+            // no blending since each frame replaces the last.
+            let mut anmf = vec![0; 6];
+            anmf.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+            anmf.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+            anmf.extend_from_slice(&(frame.delay.as_millis().min(MAX_DURATION as u128) as u32).to_le_bytes()[..3]);
+            anmf.push(ANMF_NO_BLEND);
+            for (kind, chunk) in read_chunks(&encoded).ok_or_else(malformed)? {
+                if matches!(&kind, b"ALPH" | b"VP8 " | b"VP8L") {
+                    push_chunk(&mut anmf, &kind, chunk);
+                }
+            }
+            push_chunk(&mut body, b"ANMF", &anmf);
+        }
+
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(&body);
+        std::fs::write(output_path, out)?;
+        Ok(())
+    }
+
+    /// Rewrites the file in the extended (`VP8X`) layout with `ICCP`, `EXIF`
+    /// and `XMP ` chunks. IPTC has no place in WebP and is left out.
+    fn embed_metadata(&self, output_path: &Path, metadata: &Metadata) -> Result<(), ConversionError> {
+        let data = std::fs::read(output_path)?;
+        let malformed = || ConversionError::ImageError("Written WebP has an unexpected layout".to_string());
+        let mut chunks = read_chunks(&data).ok_or_else(malformed)?;
+
+        let (mut flags, width, height) = match chunks.first() {
+            Some((kind, body)) if kind == b"VP8X" && body.len() >= 10 => {
+                let dimension = |at: usize| u32::from_le_bytes([body[at], body[at + 1], body[at + 2], 0]) + 1;
+                (body[0], dimension(4), dimension(7))
+            }
+            Some((kind, body)) if kind == b"VP8L" && body.len() >= 5 => {
+                let bits = u32::from_le_bytes([body[1], body[2], body[3], body[4]]);
+                let alpha = if bits >> 28 & 1 == 1 { VP8X_ALPHA } else { 0 };
+                (alpha, (bits & 0x3FFF) + 1, (bits >> 14 & 0x3FFF) + 1)
+            }
+            Some((kind, body)) if kind == b"VP8 " && body.len() >= 10 => {
+                let dimension = |at: usize| (u16::from_le_bytes([body[at], body[at + 1]]) & 0x3FFF) as u32;
+                (0, dimension(6), dimension(8))
+            }
+            _ => return Err(malformed()),
+        };
+        chunks.retain(|(kind, _)| !matches!(kind, b"VP8X" | b"ICCP" | b"EXIF" | b"XMP "));
+
+        flags |= if metadata.icc.is_some() { VP8X_ICC } else { 0 };
+        flags |= if metadata.exif.is_some() { VP8X_EXIF } else { 0 };
+        flags |= if metadata.xmp.is_some() { VP8X_XMP } else { 0 };
+        let mut header = vec![flags, 0, 0, 0];
+        header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+
+        // The ICC profile must directly follow VP8X; EXIF and XMP go last.
+        let mut body = b"WEBP".to_vec();
+        push_chunk(&mut body, b"VP8X", &header);
+        if let Some(icc) = &metadata.icc {
+            push_chunk(&mut body, b"ICCP", icc);
+        }
+        for (kind, chunk) in &chunks {
+            push_chunk(&mut body, kind, chunk);
+        }
+        if let Some(exif) = &metadata.exif {
+            push_chunk(&mut body, b"EXIF", exif);
+        }
+        if let Some(xmp) = &metadata.xmp {
+            push_chunk(&mut body, b"XMP ", xmp);
+        }
+
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(&body);
+        std::fs::write(output_path, out)?;
+        Ok(())
+    }
+}
+
+/// Lossless output uses the `image` encoder; lossy output goes through libwebp.
+fn encode_still(img: &DynamicImage, options: &ConversionOptions) -> Result<Vec<u8>, ConversionError> {
+    let save_error = |e: String| ConversionError::ImageError(format!("Failed to save WebP: {}", e));
+    let has_alpha = img.color().has_alpha();
+
+    if options.webp.lossless {
+        let pixels = if has_alpha { DynamicImage::ImageRgba8(img.to_rgba8()) } else { DynamicImage::ImageRgb8(img.to_rgb8()) };
+        let mut data = Vec::new();
+        WebPEncoder::new_lossless(&mut data)
+            .encode(pixels.as_bytes(), pixels.width(), pixels.height(), pixels.color().into())
+            .map_err(|e| save_error(e.to_string()))?;
+        return Ok(data);
+    }
+
+    let quality = options.webp.quality.min(100) as f32;
+    let data = if has_alpha {
+        let rgba = img.to_rgba8();
+        webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height()).encode_simple(false, quality)
+    } else {
+        let rgb = img.to_rgb8();
+        webp::Encoder::from_rgb(rgb.as_raw(), rgb.width(), rgb.height()).encode_simple(false, quality)
+    }
+    .map_err(|e| save_error(format!("{:?}", e)))?;
+    Ok(data.to_vec())
+}
+
+/// The chunks after the `RIFF`/`WEBP` header, or `None` if it's missing or a
+/// chunk is truncated.
+fn read_chunks(data: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return None;
+    }
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let kind: [u8; 4] = data[offset..offset + 4].try_into().ok()?;
+        let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().ok()?) as usize;
+        chunks.push((kind, data.get(offset + 8..offset + 8 + size)?));
+        offset += 8 + size + size % 2;
+    }
+    Some(chunks)
+}
+
+/// Writes a RIFF chunk, padded to an even length.
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
 }
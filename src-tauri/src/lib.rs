@@ -29,6 +29,8 @@ pub fn run() {
             commands::convert_from_path,
             commands::get_supported_formats,
             commands::is_supported_format,
+            commands::get_format_catalog,
+            commands::detect_format,
             commands::select_folder,
             commands::get_downloads_folder,
             commands::pick_files,
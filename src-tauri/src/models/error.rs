@@ -15,6 +15,5 @@ pub enum ConversionError {
     ImageError(String),
     
     #[error("Conversion failed: {0}")]
-    #[allow(dead_code)]
     ConversionFailed(String),
 }
@@ -1,6 +1,9 @@
 pub mod conversion_task;
 pub mod progress;
 pub mod error;
+pub mod options;
 
 pub use conversion_task::*;
 pub use error::*;
+pub use options::*;
+pub use progress::*;
@@ -0,0 +1,828 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Per-request conversion settings sent by the frontend. Every field has a
+/// default, so the frontend only needs to send the groups it changes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ConversionOptions {
+    pub jpeg: JpegOptions,
+    pub png: PngOptions,
+    pub webp: WebpOptions,
+    pub tiff: TiffOptions,
+    pub gif: GifOptions,
+    pub svg: SvgOptions,
+    pub pdf: PdfOptions,
+    pub psd: PsdOptions,
+    pub heic: HeicOptions,
+    pub avif: AvifOptions,
+    pub exr: ExrOptions,
+    pub tone_map: ToneMapOptions,
+    pub transform: TransformOptions,
+    pub transparency: TransparencyOptions,
+    pub metadata: MetadataOptions,
+    pub color: ColorOptions,
+    pub bit_depth: BitDepthOptions,
+    pub animation: AnimationOptions,
+    pub clip: ClipOptions,
+    pub sequence: SequenceOptions,
+    pub dds: DdsOptions,
+    pub fits: FitsOptions,
+    pub dicom: DicomOptions,
+    pub tga: TgaOptions,
+    pub pcx: PcxOptions,
+    pub ico: IcoOptions,
+    pub cur: CurOptions,
+    pub multi_page: MultiPageMode,
+    /// Leave pixels as stored instead of rotating them upright according to
+    /// the EXIF Orientation tag (or HEIF rotation and mirror properties).
+    pub ignore_orientation: bool,
+}
+
+/// How sources that decode to several images (e.g. PDF pages) are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MultiPageMode {
+    /// One file per image, numbered `name_001.ext`, `name_002.ext`, ...
+    #[default]
+    Separate,
+    /// A single multi-page TIFF when the target is TIFF; numbered files otherwise.
+    MultiPageTiff,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JpegOptions {
+    /// 1-100; higher is better quality and larger files.
+    pub quality: u8,
+    pub chroma: ChromaSubsampling,
+    /// Write a progressive JPEG that loads coarse-to-fine.
+    pub progressive: bool,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        JpegOptions {
+            quality: 75,
+            chroma: ChromaSubsampling::Yuv444,
+            progressive: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PngCompression {
+    #[default]
+    Fast,
+    Default,
+    Best,
+    None,
+}
+
+/// Row filter applied before compression. Adaptive picks one per row.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PngFilter {
+    #[default]
+    Adaptive,
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WebpOptions {
+    pub lossless: bool,
+    /// 0-100, used for lossy output.
+    pub quality: u8,
+}
+
+impl Default for WebpOptions {
+    fn default() -> Self {
+        WebpOptions {
+            lossless: true,
+            quality: 80,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TiffOptions {
+    pub compression: TiffCompression,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TiffCompression {
+    #[default]
+    None,
+    Lzw,
+    Deflate,
+    PackBits,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GifOptions {
+    /// Palette size, 2-256, including the transparent entry when one is needed.
+    pub colors: u16,
+    pub dither: PaletteDither,
+    pub palette: GifPalette,
+    /// Pixels with alpha below this are written as the transparent index.
+    pub alpha_threshold: u8,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {
+            colors: 256,
+            dither: PaletteDither::FloydSteinberg,
+            palette: GifPalette::PerFrame,
+            alpha_threshold: 128,
+        }
+    }
+}
+
+/// How colours between palette entries are approximated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PaletteDither {
+    /// Nearest entry only; flat areas but visible banding.
+    None,
+    /// Error diffusion; the smoothest gradients.
+    #[default]
+    FloydSteinberg,
+    /// A fixed Bayer pattern, which stays put between animation frames.
+    Ordered,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GifPalette {
+    /// One palette shared by every frame, built from all of them.
+    Global,
+    /// A palette per frame, fitted to its own colours.
+    #[default]
+    PerFrame,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SvgOptions {
+    /// Output width in pixels. When only one of width/height is set the other follows the aspect ratio.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Used when no explicit size is given; 96 renders at the document's own size.
+    pub dpi: f32,
+    /// Fill behind the drawing; transparent when unset.
+    pub background: Option<Color>,
+    /// Family used for text that names no available font.
+    pub font_family: Option<String>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            width: None,
+            height: None,
+            dpi: 96.0,
+            background: None,
+            font_family: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PdfOptions {
+    pub dpi: f32,
+    /// 1-based pages to render, e.g. `"1-3,5,8-"`. All pages when unset.
+    pub pages: Option<String>,
+    pub background: Color,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            dpi: 150.0,
+            pages: None,
+            background: Color::WHITE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PsdOptions {
+    /// Also write each visible layer as a PNG plus a `_layers.json` manifest of names and offsets.
+    pub export_layers: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HeicOptions {
+    /// Also convert non-primary images in the container (bursts, Live Photo stills) as numbered files.
+    pub include_secondary: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AvifOptions {
+    /// 0-100; higher is better quality and larger files.
+    pub quality: u8,
+    /// Encoder effort from 0 (slowest, smallest files) to 9 (fastest).
+    pub speed: u8,
+    pub chroma: ChromaSubsampling,
+    /// Encode with 10 bits per channel instead of 8.
+    pub ten_bit: bool,
+}
+
+impl Default for AvifOptions {
+    fn default() -> Self {
+        AvifOptions {
+            quality: 80,
+            speed: 6,
+            chroma: ChromaSubsampling::Yuv420,
+            ten_bit: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChromaSubsampling {
+    #[default]
+    #[serde(rename = "420")]
+    Yuv420,
+    #[serde(rename = "422")]
+    Yuv422,
+    #[serde(rename = "444")]
+    Yuv444,
+}
+
+impl ChromaSubsampling {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChromaSubsampling::Yuv420 => "420",
+            ChromaSubsampling::Yuv422 => "422",
+            ChromaSubsampling::Yuv444 => "444",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExrOptions {
+    /// Part name or channel-name prefix (`diffuse` for `diffuse.R`, ...). The first part when unset.
+    pub layer: Option<String>,
+    /// Comma-separated channel names within the layer, read as R,G,B[,A] or a single grey channel.
+    pub channels: Option<String>,
+}
+
+/// How HDR (floating-point) images are brought into range for 8-bit targets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ToneMapOptions {
+    pub operator: ToneMapOperator,
+    /// Exposure adjustment in stops, applied before the operator.
+    pub exposure: f32,
+    pub gamma: f32,
+}
+
+impl Default for ToneMapOptions {
+    fn default() -> Self {
+        ToneMapOptions {
+            operator: ToneMapOperator::ExposureGamma,
+            exposure: 0.0,
+            gamma: 2.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToneMapOperator {
+    /// Exposure and gamma only; values above 1.0 clip.
+    #[default]
+    ExposureGamma,
+    Reinhard,
+    /// ACES filmic approximation.
+    Aces,
+}
+
+/// Geometric operations applied after decoding, in order: crop, resize,
+/// rotate, flip.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TransformOptions {
+    /// Region of the source to keep, in source pixels.
+    pub crop: Option<CropRect>,
+    pub resize: Option<ResizeOptions>,
+    pub rotate: Rotation,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub filter: ResizeFilter,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ResizeOptions {
+    pub mode: ResizeMode,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Scale factor for `percent` mode; 50 halves both sides.
+    pub percent: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeMode {
+    /// Stretch to exactly `width` x `height`.
+    Exact,
+    /// Scale up or down to fit inside the box, keeping the aspect ratio.
+    /// Either side may be left out.
+    #[default]
+    Fit,
+    /// Fit inside the box, then pad to exactly `width` x `height` with
+    /// transparency (or the background colour when the target has no alpha).
+    Fill,
+    /// Scale to cover the box, then crop the overflow from the centre.
+    Cover,
+    /// Only shrink, so neither side exceeds the given limits.
+    Max,
+    /// Scale both sides by `percent`.
+    Percent,
+}
+
+/// Clockwise rotation in degrees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    #[default]
+    #[serde(rename = "0")]
+    None,
+    #[serde(rename = "90")]
+    Rotate90,
+    #[serde(rename = "180")]
+    Rotate180,
+    #[serde(rename = "270")]
+    Rotate270,
+}
+
+/// Resampling filter, from fastest to sharpest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+/// How alpha is carried into the output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TransparencyOptions {
+    /// Keep alpha when the target can store it. When off, or when it can't,
+    /// images are composited onto `background`.
+    pub keep: bool,
+    pub background: Color,
+    /// Crop away fully transparent borders before writing.
+    pub trim: bool,
+}
+
+impl Default for TransparencyOptions {
+    fn default() -> Self {
+        TransparencyOptions {
+            keep: true,
+            background: Color::WHITE,
+            trim: false,
+        }
+    }
+}
+
+/// What happens to EXIF, XMP and IPTC data in the source. The ICC profile is
+/// handled by [`ColorOptions`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MetadataOptions {
+    pub mode: MetadataMode,
+    /// Drop GPS position tags from copied EXIF and XMP.
+    pub remove_gps: bool,
+    /// Drop camera and lens serial numbers, along with EXIF maker notes,
+    /// which often contain them.
+    pub remove_serial_numbers: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MetadataMode {
+    /// Write no EXIF, XMP or IPTC.
+    #[default]
+    Strip,
+    /// Copy whatever the target container can hold (JPEG, PNG, WebP, TIFF).
+    Copy,
+}
+
+/// ICC colour management. Sources without a profile are treated as sRGB.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ColorOptions {
+    /// Convert pixels from the source profile to `target`.
+    pub convert: bool,
+    pub target: TargetProfile,
+    /// An ICC file to convert to instead of a built-in `target`.
+    pub target_profile_path: Option<String>,
+    pub intent: RenderingIntent,
+    /// Embed the output's profile (the target when converting, otherwise the
+    /// source's own) in PNG, JPEG, TIFF and WebP.
+    pub embed_profile: bool,
+}
+
+impl Default for ColorOptions {
+    fn default() -> Self {
+        ColorOptions {
+            convert: false,
+            target: TargetProfile::Srgb,
+            target_profile_path: None,
+            intent: RenderingIntent::Perceptual,
+            embed_profile: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TargetProfile {
+    #[default]
+    Srgb,
+    DisplayP3,
+    AdobeRgb,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RenderingIntent {
+    #[default]
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+/// How 16-bit and float samples are reduced for targets that store fewer bits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BitDepthOptions {
+    /// Apply ordered dithering when reducing to 8 bits, which hides banding
+    /// in smooth gradients. When off, samples are rounded.
+    pub dither: bool,
+}
+
+impl Default for BitDepthOptions {
+    fn default() -> Self {
+        BitDepthOptions { dither: true }
+    }
+}
+
+/// Applied when an animated GIF, APNG or WebP is written to one of those formats.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AnimationOptions {
+    /// Resample to this many frames per second, dropping or repeating source
+    /// frames so the running time stays the same. Unset keeps the source timing.
+    pub frame_rate: Option<f32>,
+    /// Times the animation plays, with 0 looping forever. Unset keeps the
+    /// source's setting.
+    pub loop_count: Option<u32>,
+    /// Size factor applied to every frame after `transform`, e.g. 0.5 for half size.
+    pub scale: f32,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        AnimationOptions {
+            frame_rate: None,
+            loop_count: None,
+            scale: 1.0,
+        }
+    }
+}
+
+/// The part of a video turned into a GIF, WebP or APNG animation. Frame
+/// rate and loop count come from [`AnimationOptions`], and the palette from
+/// [`GifOptions`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ClipOptions {
+    /// Offset into the video, in seconds.
+    pub start: f64,
+    /// Length of the clip in seconds; unset runs to the end.
+    pub duration: Option<f64>,
+    /// Output width in pixels, keeping the aspect ratio; unset keeps the video's.
+    pub width: Option<u32>,
+}
+
+/// Bridges between image sequences and video: a folder of numbered frames
+/// written as a video, or a video's frames written as images.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SequenceOptions {
+    /// Playback rate for a folder of frames written as video.
+    pub frame_rate: f32,
+    /// Export every Nth frame of a video; 1 exports them all.
+    pub every_nth: u32,
+    /// Export the frames at these times, in seconds, instead of every Nth.
+    pub timestamps: Vec<f64>,
+    /// Name for exported frames, without extension. `{name}` is the video's
+    /// file name and `{n}` the frame's number, zero-padded with e.g. `{n:05}`.
+    pub name_template: String,
+}
+
+impl Default for SequenceOptions {
+    fn default() -> Self {
+        SequenceOptions {
+            frame_rate: 24.0,
+            every_nth: 1,
+            timestamps: Vec::new(),
+            name_template: "{name}_{n:05}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DdsOptions {
+    pub compression: DdsCompression,
+    /// Tag colour formats as sRGB. Ignored by BC4, BC5 and BC6H.
+    pub srgb: bool,
+    pub quality: DdsQuality,
+    /// Generate the full mipmap chain on export.
+    pub mipmaps: bool,
+    /// How the six faces of a cube map are written on import.
+    pub faces: DdsFaceLayout,
+}
+
+impl Default for DdsOptions {
+    fn default() -> Self {
+        DdsOptions {
+            compression: DdsCompression::Bc7,
+            srgb: true,
+            quality: DdsQuality::Normal,
+            mipmaps: true,
+            faces: DdsFaceLayout::Separate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DdsCompression {
+    /// RGB with 1-bit alpha (DXT1).
+    Bc1,
+    /// Explicit 4-bit alpha (DXT3).
+    Bc2,
+    /// Interpolated alpha (DXT5).
+    Bc3,
+    /// Single channel.
+    Bc4,
+    /// Two channels, typically normal maps.
+    Bc5,
+    /// HDR RGB.
+    Bc6h,
+    #[default]
+    Bc7,
+    /// Plain RGBA8.
+    Uncompressed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DdsQuality {
+    Fast,
+    #[default]
+    Normal,
+    Slow,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DdsFaceLayout {
+    /// One numbered image per array layer or cube face.
+    #[default]
+    Separate,
+    /// Cube maps as a single 4x3 horizontal cross.
+    Cross,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FitsOptions {
+    /// HDU index (0 is the primary) or `EXTNAME`. The first HDU holding image data when unset.
+    pub hdu: Option<String>,
+    /// Plane of a data cube (NAXIS3 and beyond) to show.
+    pub plane: usize,
+    pub stretch: FitsStretch,
+    /// Percentiles clipped to black and white before stretching. Ignored by zscale.
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    /// Produce 16-bit greyscale instead of 8-bit.
+    pub sixteen_bit: bool,
+    /// Also write the HDU's header cards to a `_header.json` file.
+    pub export_header: bool,
+}
+
+impl Default for FitsOptions {
+    fn default() -> Self {
+        FitsOptions {
+            hdu: None,
+            plane: 0,
+            stretch: FitsStretch::Linear,
+            low_percentile: 0.5,
+            high_percentile: 99.5,
+            sixteen_bit: false,
+            export_header: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FitsStretch {
+    #[default]
+    Linear,
+    Log,
+    Sqrt,
+    Asinh,
+    /// IRAF zscale limits, which suit faint detail around the sky background.
+    Zscale,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DicomOptions {
+    /// Window center and width overriding the file's VOI settings. Both must be set.
+    pub window_center: Option<f64>,
+    pub window_width: Option<f64>,
+    /// 1-based frames of a multi-frame study, e.g. `"1-10"`. All frames when unset.
+    pub frames: Option<String>,
+    /// Frame rate for video output. Taken from the file, or 10 fps, when unset.
+    pub frame_rate: Option<f32>,
+    /// Produce 16-bit output instead of 8-bit.
+    pub sixteen_bit: bool,
+    /// Also write the data set's attributes to a `_metadata.json` file.
+    pub export_metadata: bool,
+    /// Leave patient-identifying and private attributes out of the exported metadata.
+    pub anonymize: bool,
+}
+
+impl Default for DicomOptions {
+    fn default() -> Self {
+        DicomOptions {
+            window_center: None,
+            window_width: None,
+            frames: None,
+            frame_rate: None,
+            sixteen_bit: false,
+            export_metadata: false,
+            anonymize: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TgaOptions {
+    /// Run-length encode the pixel data.
+    pub rle: bool,
+    pub origin: TgaOrigin,
+}
+
+impl Default for TgaOptions {
+    fn default() -> Self {
+        TgaOptions {
+            rle: true,
+            origin: TgaOrigin::BottomLeft,
+        }
+    }
+}
+
+/// Corner the first stored row starts at. Bottom-left is the traditional
+/// layout most older tools expect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TgaOrigin {
+    #[default]
+    BottomLeft,
+    TopLeft,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PcxOptions {
+    pub colors: PcxColors,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PcxColors {
+    /// 8-bit palettized when the image has at most 256 colours, 24-bit otherwise.
+    #[default]
+    Auto,
+    /// Always 8-bit palettized, reducing to 256 colours if needed.
+    Indexed,
+    /// 24-bit RGB in three planes.
+    Rgb,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct IcoOptions {
+    /// Square sizes written to ICO and CUR files, each resampled from the source. 1-256.
+    pub sizes: Vec<u32>,
+    pub entry_format: IcoEntryFormat,
+    /// Convert every image embedded in an ICO or CUR as numbered files, not just the largest.
+    pub extract_all: bool,
+}
+
+impl Default for IcoOptions {
+    fn default() -> Self {
+        IcoOptions {
+            sizes: vec![16, 24, 32, 48, 64, 128, 256],
+            entry_format: IcoEntryFormat::Auto,
+            extract_all: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IcoEntryFormat {
+    /// PNG for 256px entries and BMP below, which every Windows version can read.
+    #[default]
+    Auto,
+    Png,
+    Bmp,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CurOptions {
+    /// Cursor hotspot in source image pixels, scaled to each size.
+    pub hotspot_x: u32,
+    pub hotspot_y: u32,
+}
+
+/// An RGBA colour, written as `#rrggbb` or `#rrggbbaa` on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub [u8; 4]);
+
+impl Color {
+    pub const WHITE: Color = Color([255, 255, 255, 255]);
+
+    pub fn parse(value: &str) -> Option<Color> {
+        let hex = value.trim().trim_start_matches('#');
+        let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+
+        match hex.len() {
+            6 => Some(Color([byte(0)?, byte(2)?, byte(4)?, 255])),
+            8 => Some(Color([byte(0)?, byte(2)?, byte(4)?, byte(6)?])),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = self.0;
+        serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Color::parse(&value)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid colour: {}", value)))
+    }
+}
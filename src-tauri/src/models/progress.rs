@@ -5,4 +5,6 @@ pub struct ProgressUpdate {
     pub id: String,
     pub percentage: u32,
     pub message: String,
+    /// Estimated seconds left, once the job is far enough along to tell.
+    pub eta_seconds: Option<u64>,
 }
@@ -1,6 +1,7 @@
 import { defineStore } from "pinia";
 import { ref } from "vue";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { useNotificationStore } from "./notifications";
 
 export interface FileItem {
@@ -15,6 +16,97 @@ export interface FileItem {
   preview?: string;
 }
 
+/** Settings shown in the options panel; mapped onto the backend's ConversionOptions. */
+export interface ConversionSettings {
+  jpegQuality: number;
+  jpegChroma: "420" | "422" | "444";
+  jpegProgressive: boolean;
+  pngCompression: "fast" | "default" | "best" | "none";
+  webpLossless: boolean;
+  webpQuality: number;
+  tiffCompression: "none" | "lzw" | "deflate" | "packBits";
+  gifColors: number;
+  gifDither: "none" | "floydSteinberg" | "ordered";
+  resizeWidth: number | "";
+  resizeHeight: number | "";
+  rotate: "0" | "90" | "180" | "270";
+  flipHorizontal: boolean;
+  flipVertical: boolean;
+  copyMetadata: boolean;
+  removeGps: boolean;
+  convertToSrgb: boolean;
+  ditherDepth: boolean;
+  frameRate: number | "";
+  loopCount: number | "";
+  clipStart: number;
+  clipDuration: number | "";
+  clipWidth: number | "";
+  sequenceFrameRate: number;
+  everyNth: number;
+}
+
+const defaultSettings = (): ConversionSettings => ({
+  jpegQuality: 75,
+  jpegChroma: "444",
+  jpegProgressive: false,
+  pngCompression: "default",
+  webpLossless: true,
+  webpQuality: 80,
+  tiffCompression: "none",
+  gifColors: 256,
+  gifDither: "floydSteinberg",
+  resizeWidth: "",
+  resizeHeight: "",
+  rotate: "0",
+  flipHorizontal: false,
+  flipVertical: false,
+  copyMetadata: false,
+  removeGps: false,
+  convertToSrgb: false,
+  ditherDepth: true,
+  frameRate: "",
+  loopCount: "",
+  clipStart: 0,
+  clipDuration: "",
+  clipWidth: "",
+  sequenceFrameRate: 24,
+  everyNth: 1,
+});
+
+/** Empty number inputs leave the option unset so the backend default applies. */
+const optional = (value: number | "") => (value === "" || Number.isNaN(value) ? null : value);
+
+const toConversionOptions = (settings: ConversionSettings) => {
+  const width = optional(settings.resizeWidth);
+  const height = optional(settings.resizeHeight);
+  return {
+    jpeg: { quality: settings.jpegQuality, chroma: settings.jpegChroma, progressive: settings.jpegProgressive },
+    png: { compression: settings.pngCompression },
+    webp: { lossless: settings.webpLossless, quality: settings.webpQuality },
+    tiff: { compression: settings.tiffCompression },
+    gif: { colors: settings.gifColors, dither: settings.gifDither },
+    transform: {
+      resize: width !== null || height !== null ? { mode: "fit", width, height } : null,
+      rotate: settings.rotate,
+      flipHorizontal: settings.flipHorizontal,
+      flipVertical: settings.flipVertical,
+    },
+    metadata: { mode: settings.copyMetadata ? "copy" : "strip", removeGps: settings.removeGps },
+    color: { convert: settings.convertToSrgb, target: "srgb" },
+    bitDepth: { dither: settings.ditherDepth },
+    animation: { frameRate: optional(settings.frameRate), loopCount: optional(settings.loopCount) },
+    clip: { start: settings.clipStart || 0, duration: optional(settings.clipDuration), width: optional(settings.clipWidth) },
+    sequence: { frameRate: settings.sequenceFrameRate || 24, everyNth: settings.everyNth || 1 },
+  };
+};
+
+interface ProgressUpdate {
+  id: string;
+  percentage: number;
+  message: string;
+  eta_seconds: number | null;
+}
+
 interface HistoryItem {
   id: string;
   fromFormat: string;
@@ -27,6 +119,7 @@ export const useConversionStore = defineStore("conversion", () => {
   const files = ref<FileItem[]>([]);
   const history = ref<HistoryItem[]>([]);
   const isProcessing = ref(false);
+  const settings = ref<ConversionSettings>(defaultSettings());
   const currentProgress = ref({
     percentage: 0,
     message: "Starting conversion...",
@@ -71,6 +164,7 @@ export const useConversionStore = defineStore("conversion", () => {
     }
 
     isProcessing.value = true;
+    const options = toConversionOptions(settings.value);
     const totalFiles = files.value.length;
     const notificationStore = useNotificationStore();
     let successCount = 0;
@@ -78,6 +172,8 @@ export const useConversionStore = defineStore("conversion", () => {
 
     for (let i = 0; i < files.value.length; i++) {
       const file = files.value[i];
+      // Progress events carry this id, so a late event from another job is ignored.
+      const jobId = crypto.randomUUID();
       const isVideoFile = ['mp4', 'mkv', 'mov', 'webm', 'avi', 'flv', 'mpg', 'mpeg', 'ts', 'm2ts', 'mts', 'ogv', 'ogg'].includes(file.extension.toLowerCase());
       
       currentProgress.value = {
@@ -106,6 +202,8 @@ export const useConversionStore = defineStore("conversion", () => {
             fromFormat: file.extension,
             toFormat: file.targetFormat,
             keepTransparency: keepTransparency,
+            options,
+            jobId,
           });
           
           currentProgress.value = {
@@ -127,25 +225,27 @@ export const useConversionStore = defineStore("conversion", () => {
           }
         } else {
           if (isVideoFile) {
-            let fakeProgress = 0;
-            const progressInterval = setInterval(() => {
-              fakeProgress += Math.random() * 15;
-              if (fakeProgress > 95) fakeProgress = 95;
-              
+            const unlisten = await listen<ProgressUpdate>("conversion-progress", (event) => {
+              if (event.payload.id !== jobId) {
+                return;
+              }
+              const { percentage, eta_seconds } = event.payload;
+              const eta = eta_seconds != null ? `, ${eta_seconds}s left` : "";
               currentProgress.value = {
-                percentage: Math.round(fakeProgress),
-                message: `Processing ${i + 1}/${totalFiles}: ${file.name}... (${Math.round(fakeProgress)}%)`,
+                percentage,
+                message: `Processing ${i + 1}/${totalFiles}: ${file.name}... (${percentage}%${eta})`,
               };
-            }, 500);
+            });
 
             const result: any = await invoke("convert_from_path", {
               inputPath: inputPath,
               outputPath: outputPath,
               fromFormat: file.extension,
               toFormat: file.targetFormat,
-            });
-
-            clearInterval(progressInterval);
+              keepTransparency: keepTransparency,
+              options,
+              jobId,
+            }).finally(unlisten);
 
             if (result.success) {
               successCount++;
@@ -174,6 +274,9 @@ export const useConversionStore = defineStore("conversion", () => {
               outputPath: outputPath,
               fromFormat: file.extension,
               toFormat: file.targetFormat,
+              keepTransparency: keepTransparency,
+              options,
+              jobId,
             });
 
             if (result.success) {
@@ -232,6 +335,7 @@ export const useConversionStore = defineStore("conversion", () => {
     history,
     isProcessing,
     currentProgress,
+    settings,
     addFile,
     removeFile,
     clearFiles,